@@ -0,0 +1,12 @@
+create table invites
+(
+    id         integer primary key auto_increment not null,
+    code       varchar(32) not null,
+    max_uses   int         not null,
+    uses       int         not null default 0,
+    created_at bigint      not null,
+    expires_at bigint      null
+);
+
+create unique index invites_code_uindex
+    on invites (code);
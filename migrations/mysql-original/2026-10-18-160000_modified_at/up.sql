@@ -0,0 +1,11 @@
+-- server-side modification time, used for delta sync
+alter table categories add column modified_at bigint not null default 0;
+alter table favourites add column modified_at bigint not null default 0;
+alter table history add column modified_at bigint not null default 0;
+
+create index categories_user_id_modified_at_index
+    on categories (user_id, modified_at);
+create index favourites_user_id_modified_at_index
+    on favourites (user_id, modified_at);
+create index history_user_id_modified_at_index
+    on history (user_id, modified_at);
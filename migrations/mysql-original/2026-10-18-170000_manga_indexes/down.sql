@@ -0,0 +1,5 @@
+drop index manga_source_index on manga;
+drop index manga_state_index on manga;
+drop index manga_content_rating_index on manga;
+drop index manga_author_index on manga;
+drop index tags_key_index on tags;
@@ -0,0 +1 @@
+drop index manga_title_fulltext_index on manga;
@@ -0,0 +1,3 @@
+-- full-text search by title
+create fulltext index manga_title_fulltext_index
+    on manga (title, alt_title);
@@ -0,0 +1,5 @@
+alter table sessions add column device_name varchar(100) null;
+alter table sessions add column user_agent varchar(255) null;
+alter table sessions add column last_seen_at bigint not null default 0;
+
+update sessions set last_seen_at = created_at;
@@ -0,0 +1,15 @@
+create table password_resets
+(
+    id         integer primary key auto_increment not null,
+    user_id    int      not null,
+    token_hash char(64) not null,
+    created_at bigint   not null,
+    expires_at bigint   not null,
+    used_at    bigint   null,
+    constraint password_resets_ibfk_1
+        foreign key (user_id) references users (id)
+            on delete cascade
+);
+
+create unique index password_resets_token_hash_uindex
+    on password_resets (token_hash);
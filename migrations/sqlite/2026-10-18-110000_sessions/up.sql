@@ -0,0 +1,28 @@
+create table sessions
+(
+    id         integer primary key autoincrement not null,
+    user_id    int    not null,
+    created_at bigint not null,
+    revoked_at bigint,
+    constraint sessions_ibfk_1
+        foreign key (user_id) references users (id)
+            on delete cascade
+);
+
+create index sessions_user_id_index
+    on sessions (user_id);
+
+create table refresh_tokens
+(
+    id         integer primary key autoincrement not null,
+    session_id int    not null,
+    token_hash text   not null,
+    expires_at bigint not null,
+    used_at    bigint,
+    constraint refresh_tokens_ibfk_1
+        foreign key (session_id) references sessions (id)
+            on delete cascade
+);
+
+create unique index refresh_tokens_token_hash_uindex
+    on refresh_tokens (token_hash);
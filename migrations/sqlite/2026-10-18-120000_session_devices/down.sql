@@ -0,0 +1,3 @@
+alter table sessions drop column device_name;
+alter table sessions drop column user_agent;
+alter table sessions drop column last_seen_at;
@@ -0,0 +1,12 @@
+create table invites
+(
+    id         integer primary key autoincrement not null,
+    code       text   not null,
+    max_uses   int    not null,
+    uses       int    not null default 0,
+    created_at bigint not null,
+    expires_at bigint
+);
+
+create unique index invites_code_uindex
+    on invites (code);
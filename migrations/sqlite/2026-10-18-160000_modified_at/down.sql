@@ -0,0 +1,7 @@
+drop index categories_user_id_modified_at_index;
+drop index favourites_user_id_modified_at_index;
+drop index history_user_id_modified_at_index;
+
+alter table categories drop column modified_at;
+alter table favourites drop column modified_at;
+alter table history drop column modified_at;
@@ -0,0 +1,5 @@
+drop index manga_source_index;
+drop index manga_state_index;
+drop index manga_content_rating_index;
+drop index manga_author_index;
+drop index tags_key_index;
@@ -0,0 +1,11 @@
+-- filters of manga list
+create index manga_source_index
+    on manga (source);
+create index manga_state_index
+    on manga (state);
+create index manga_content_rating_index
+    on manga (content_rating);
+create index manga_author_index
+    on manga (author);
+create index tags_key_index
+    on tags (`key`);
@@ -0,0 +1 @@
+drop table manga_fts;
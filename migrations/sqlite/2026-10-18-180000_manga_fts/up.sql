@@ -0,0 +1,11 @@
+-- full-text search by title, kept in sync when manga is saved
+create virtual table manga_fts using fts5
+(
+    title,
+    alt_title,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+insert into manga_fts (rowid, title, alt_title)
+select id, title, alt_title
+from manga;
@@ -0,0 +1,174 @@
+//! Kotatsu app backup archives
+
+use std::{
+    collections::HashSet,
+    io::{Cursor, Read, Write},
+};
+
+use anyhow::{Result, anyhow};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::value::RawValue;
+
+use crate::models::{
+    backup,
+    common::{FavouritesPackage, HistoryPackage, Time},
+    response::{BackupImport, ImportCount},
+};
+
+const INDEX: &str = "index";
+const CATEGORIES: &str = "categories";
+const FAVOURITES: &str = "favourites";
+const HISTORY: &str = "history";
+
+/// Max total size of unpacked entries relative to upload limit, to not run out
+/// of memory on malicious archive
+const MAX_UNPACKED_RATIO: u64 = 4;
+
+/// Synced parts of backup
+pub struct Backup {
+    pub favourites: FavouritesPackage,
+    pub history: HistoryPackage,
+    pub report: BackupImport,
+}
+
+/// Read backup zip, which is not larger than `limit`. Entries, which can't be
+/// parsed, are skipped and counted in report
+pub fn read(bytes: &[u8], limit: u64) -> Result<Backup> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut unpacked_left = limit.saturating_mul(MAX_UNPACKED_RATIO);
+
+    let mut categories = None;
+    let mut favourites = None;
+    let mut history = None;
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        // entries have no extension, but allowing it for manually packed ones
+        let name = file.name().trim_end_matches(".json").to_string();
+        let target = match name.as_str() {
+            CATEGORIES => &mut categories,
+            FAVOURITES => &mut favourites,
+            HISTORY => &mut history,
+            _ => continue,
+        };
+
+        let mut content = String::new();
+        file.take(unpacked_left + 1).read_to_string(&mut content)?;
+        unpacked_left = unpacked_left
+            .checked_sub(content.len() as u64)
+            .ok_or_else(|| anyhow!("archive is too large when unpacked"))?;
+        *target = Some(content);
+    }
+    if categories.is_none() && favourites.is_none() && history.is_none() {
+        return Err(anyhow!("no favourites or history in archive"));
+    }
+
+    let (categories, categories_count) =
+        parse::<backup::Category>(categories.as_deref(), CATEGORIES)?;
+    let (mut favourites, mut favourites_count) =
+        parse::<backup::Favourite>(favourites.as_deref(), FAVOURITES)?;
+    let (history, history_count) = parse::<backup::History>(history.as_deref(), HISTORY)?;
+
+    // favourite can't be saved without its category
+    let category_ids: HashSet<_> = categories.iter().map(|c| c.category_id).collect();
+    favourites.retain(|f| {
+        let keep = category_ids.contains(&f.category_id);
+        if !keep {
+            log::debug!("skipping favourite without category {}", f.category_id);
+            favourites_count.parsed -= 1;
+            favourites_count.skipped += 1;
+        }
+        keep
+    });
+
+    Ok(Backup {
+        favourites: FavouritesPackage {
+            categories: categories.iter().map(|c| c.to_api()).collect(),
+            favourites: favourites.iter().map(|f| f.to_api()).collect(),
+            timestamp: None,
+        },
+        history: HistoryPackage {
+            history: history.iter().map(|h| h.to_api()).collect(),
+            timestamp: None,
+        },
+        report: BackupImport {
+            categories: categories_count,
+            favourites: favourites_count,
+            history: history_count,
+        },
+    })
+}
+
+/// Parse JSON array of entries. Only array itself is required to be valid,
+/// each entry is parsed separately, so invalid ones are skipped
+fn parse<T: DeserializeOwned>(content: Option<&str>, name: &str) -> Result<(Vec<T>, ImportCount)> {
+    let mut count = ImportCount::default();
+    let Some(content) = content else {
+        return Ok((vec![], count));
+    };
+    let entries: Vec<&RawValue> = serde_json::from_str(content)?;
+    let items = entries
+        .into_iter()
+        .filter_map(|v| {
+            serde_json::from_str(v.get())
+                .inspect(|_| count.parsed += 1)
+                .inspect_err(|e| {
+                    log::debug!("skipping invalid {name} entry: {e}");
+                    count.skipped += 1;
+                })
+                .ok()
+        })
+        .collect();
+    Ok((items, count))
+}
+
+/// Write backup zip, which can be restored in Kotatsu app. Deleted items and
+/// favourites in deleted categories are not included
+pub fn write(
+    favourites: &FavouritesPackage,
+    history: &HistoryPackage,
+    created_at: Time,
+) -> Result<Vec<u8>> {
+    let index = vec![backup::Index {
+        app_id: "org.koitharu.kotatsu".to_string(),
+        app_version: 0,
+        created_at,
+    }];
+    let categories: Vec<_> = favourites
+        .categories
+        .iter()
+        .filter(|c| c.deleted_at == 0)
+        .map(backup::Category::from_api)
+        .collect();
+    // deleted category is kept while it has favourites, but app can't
+    // restore them without it
+    let category_ids: HashSet<_> = categories.iter().map(|c| c.category_id).collect();
+    let favourites: Vec<_> = favourites
+        .favourites
+        .iter()
+        .filter(|f| f.deleted_at == 0 && category_ids.contains(&f.category_id))
+        .map(backup::Favourite::from_api)
+        .collect();
+    let history: Vec<_> = history
+        .history
+        .iter()
+        .filter(|h| h.deleted_at == 0)
+        .map(backup::History::from_api)
+        .collect();
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    write_entry(&mut archive, INDEX, &index)?;
+    write_entry(&mut archive, CATEGORIES, &categories)?;
+    write_entry(&mut archive, FAVOURITES, &favourites)?;
+    write_entry(&mut archive, HISTORY, &history)?;
+    Ok(archive.finish()?.into_inner())
+}
+
+fn write_entry<T: Serialize>(
+    archive: &mut zip::ZipWriter<Cursor<Vec<u8>>>,
+    name: &str,
+    items: &[T],
+) -> Result<()> {
+    archive.start_file(name, zip::write::SimpleFileOptions::default())?;
+    archive.write_all(&serde_json::to_vec(items)?)?;
+    Ok(())
+}
@@ -0,0 +1,187 @@
+//! Commands for managing server from shell
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Write},
+};
+
+use anyhow::{Context, Result, anyhow};
+use clap::{Parser, Subcommand};
+use zeroize::Zeroizing;
+
+use crate::{
+    config::ConfDB,
+    db::conn::DB,
+    models::{db::User, request},
+};
+
+const USERS_PAGE_SIZE: usize = 1000;
+
+/// Synchronization server for Kotatsu
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Default, Subcommand)]
+pub enum Command {
+    /// Start server. Default command
+    #[default]
+    Serve,
+    /// Run pending database migrations without starting server
+    Migrate {
+        /// Roll back the last applied migration instead
+        #[arg(long)]
+        revert: bool,
+    },
+    /// Manage users
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Load config and print it with secrets redacted
+    CheckConfig,
+    /// Write all data into portable dump
+    Export {
+        /// File to write, `-` for stdout
+        path: String,
+    },
+    /// Load dump into empty database
+    Import {
+        /// File to read, `-` for stdin
+        path: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UserCommand {
+    /// Create user. Password is read from stdin
+    Add { email: String },
+    /// List all users
+    List,
+    /// Delete user with all data
+    Delete { email: String },
+    /// Change password and revoke all sessions. Password is read from stdin
+    SetPassword { email: String },
+}
+
+/// Run or roll back migrations. Connects without applying migrations, so
+/// the last one can be reverted
+pub fn migrate(db_conf: ConfDB, revert: bool) -> Result<()> {
+    let db = DB::open(db_conf)?;
+    if revert {
+        let version = db.revert_migration()?;
+        eprintln!("reverted migration {version}");
+        return Ok(());
+    }
+
+    let applied = db.migrate()?;
+    if applied.is_empty() {
+        eprintln!("no pending migrations");
+    }
+    for version in applied {
+        eprintln!("applied migration {version}");
+    }
+    Ok(())
+}
+
+pub fn user(db: &DB, command: UserCommand) -> Result<()> {
+    match command {
+        UserCommand::Add { email } => {
+            let user = add_user(db, &email, &read_password()?)?;
+            eprintln!("created user {}", user.id);
+        }
+        UserCommand::List => {
+            let mut out = std::io::stdout().lock();
+            writeln!(out, "id\temail\tfavourites\thistory\tdisabled")?;
+            for offset in (0..).step_by(USERS_PAGE_SIZE) {
+                let users = db.list_users(offset, USERS_PAGE_SIZE)?;
+                for u in &users {
+                    writeln!(
+                        out,
+                        "{}\t{}\t{}\t{}\t{}",
+                        u.id,
+                        u.email,
+                        u.favourites_count,
+                        u.history_count,
+                        u.disabled_at.is_some(),
+                    )?;
+                }
+                if users.len() < USERS_PAGE_SIZE {
+                    break;
+                }
+            }
+        }
+        UserCommand::Delete { email } => {
+            let user = find_user(db, &email)?;
+            db.delete_user(user.id)?;
+            eprintln!("deleted user {}", user.id);
+        }
+        UserCommand::SetPassword { email } => {
+            let user = set_password(db, &email, &read_password()?)?;
+            eprintln!("changed password of user {}", user.id);
+        }
+    }
+    Ok(())
+}
+
+pub fn add_user(db: &DB, email: &str, password: &str) -> Result<User> {
+    request::validate_email(email).map_err(|e| anyhow!(e))?;
+    request::validate_password(password).map_err(|e| anyhow!(e))?;
+    if db.get_user_by_email(email)?.is_some() {
+        return Err(anyhow!("user {email} already exists"));
+    }
+    db.create_user(email, &request::hash_password(password)?)
+}
+
+/// Change password and revoke all sessions, as after password reset
+pub fn set_password(db: &DB, email: &str, password: &str) -> Result<User> {
+    request::validate_password(password).map_err(|e| anyhow!(e))?;
+    let user = find_user(db, email)?;
+    db.update_user_password(user.id, &request::hash_password(password)?)?;
+    db.revoke_sessions(user.id)?;
+    Ok(user)
+}
+
+fn find_user(db: &DB, email: &str) -> Result<User> {
+    db.get_user_by_email(email)?
+        .ok_or_else(|| anyhow!("user {email} not found"))
+}
+
+/// Read password from the first line of stdin, to not leave it in shell
+/// history
+fn read_password() -> Result<Zeroizing<String>> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprint!("Password: ");
+    }
+    let mut password = Zeroizing::new(String::new());
+    stdin.lock().read_line(&mut password)?;
+    let len = password.trim_end_matches(['\r', '\n']).len();
+    password.truncate(len);
+    Ok(password)
+}
+
+/// Write dump of database into file, or stdout for `-`
+pub fn export(db: &DB, path: &str) -> Result<()> {
+    let mut out: Box<dyn Write> = match path {
+        "-" => Box::new(std::io::stdout().lock()),
+        path => Box::new(File::create(path).with_context(|| format!("failed to create {path}"))?),
+    };
+    let mut out = BufWriter::new(&mut out);
+    let count = db.dump(&mut out)?;
+    out.flush()?;
+    eprintln!("exported {count} rows");
+    Ok(())
+}
+
+/// Load dump into empty database from file, or stdin for `-`
+pub fn import(db: &DB, path: &str) -> Result<()> {
+    let input: Box<dyn Read> = match path {
+        "-" => Box::new(std::io::stdin().lock()),
+        path => Box::new(File::open(path).with_context(|| format!("failed to open {path}"))?),
+    };
+    let count = db.load(BufReader::new(input))?;
+    eprintln!("imported {count} rows");
+    Ok(())
+}
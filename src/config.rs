@@ -12,6 +12,12 @@ pub struct Conf {
     pub db: ConfDB,
     #[config(nested)]
     pub jwt: ConfJWT,
+    #[config(nested)]
+    pub gc: ConfGC,
+    #[config(nested)]
+    pub jobs: ConfJobs,
+    #[config(nested)]
+    pub backup: ConfBackup,
 }
 
 #[derive(Debug, Clone, confique::Config)]
@@ -20,16 +26,52 @@ pub struct ConfServer {
     pub port: u16,
     #[config(env = "ADMIN_API")]
     pub admin_api: Option<String>,
+    /// Bearer token for admin API, required if admin API is enabled
+    #[config(env = "ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
     #[config(env = "ALLOW_NEW_REGISTER", default = true)]
     pub allow_new_register: bool,
+    /// Register new users only via `/register`, instead of creating them on
+    /// `/auth` with unknown email
+    #[config(env = "SEPARATE_REGISTER", default = false)]
+    pub separate_register: bool,
+    /// Header with client IP, set by trusted reverse proxy, like `X-Real-IP`.
+    /// If not set, IP of connection is used, because clients can send any
+    /// header
+    #[config(env = "IP_HEADER")]
+    pub ip_header: Option<String>,
     #[config(nested)]
     pub limits: ConfServerLimits,
+    #[config(nested)]
+    pub rate_limit: ConfServerRateLimit,
 }
 
 #[derive(Debug, Clone, confique::Config)]
 pub struct ConfServerLimits {
     #[config(env = "LIMITS_JSON", default = "4MiB")]
     pub json: ByteUnit,
+    /// Max size of uploaded backup
+    #[config(env = "LIMITS_BACKUP", default = "32MiB")]
+    pub backup: ByteUnit,
+}
+
+/// Limits for failed login attempts
+#[derive(Debug, Clone, confique::Config)]
+pub struct ConfServerRateLimit {
+    #[config(env = "RATE_LIMIT_ENABLED", default = true)]
+    pub enabled: bool,
+    /// Sliding window in seconds
+    #[config(env = "RATE_LIMIT_WINDOW", default = 600)]
+    pub window: u64,
+    #[config(env = "RATE_LIMIT_MAX_PER_EMAIL", default = 5)]
+    pub max_per_email: u32,
+    #[config(env = "RATE_LIMIT_MAX_PER_IP", default = 20)]
+    pub max_per_ip: u32,
+    /// Lockout time in seconds, doubled on each lockout in a row
+    #[config(env = "RATE_LIMIT_LOCKOUT", default = 60)]
+    pub lockout: u64,
+    #[config(env = "RATE_LIMIT_MAX_LOCKOUT", default = 3600)]
+    pub max_lockout: u64,
 }
 
 #[derive(Debug, Clone, confique::Config)]
@@ -38,6 +80,51 @@ pub struct ConfJWT {
     pub secret: String,
     #[config(env = "JWT_ISSUER", default = "http://0.0.0.0:8080")]
     pub issuer: String,
+    /// Lifetime of access token in seconds
+    #[config(env = "JWT_ACCESS_LIFETIME", default = 86400)]
+    pub access_lifetime: u64,
+    /// Lifetime of refresh token in seconds
+    #[config(env = "JWT_REFRESH_LIFETIME", default = 2592000)]
+    pub refresh_lifetime: u64,
+}
+
+/// Removal of old soft-deleted rows and manga, which no user references
+#[derive(Debug, Clone, confique::Config)]
+pub struct ConfGC {
+    #[config(env = "GC_ENABLED", default = true)]
+    pub enabled: bool,
+    /// How long deleted favourites, categories and history are kept in
+    /// seconds, so other devices can receive deletion
+    #[config(env = "GC_RETENTION", default = 7776000)]
+    pub retention: u64,
+    /// Interval between runs in seconds
+    #[config(env = "GC_INTERVAL", default = 86400)]
+    pub interval: u64,
+}
+
+/// Intervals of periodic maintenance jobs in seconds, 0 disables job
+#[derive(Debug, Clone, confique::Config)]
+pub struct ConfJobs {
+    /// Removal of expired and revoked sessions
+    #[config(env = "JOBS_PRUNE_SESSIONS_INTERVAL", default = 86400)]
+    pub prune_sessions_interval: u64,
+    /// `VACUUM` and `ANALYZE` for SQLite, `OPTIMIZE TABLE` for MySQL
+    #[config(env = "JOBS_OPTIMIZE_DB_INTERVAL", default = 604800)]
+    pub optimize_db_interval: u64,
+}
+
+/// Copies of SQLite database, made while server is running
+#[derive(Debug, Clone, confique::Config)]
+pub struct ConfBackup {
+    /// Directory for backups. Backups are disabled, if not set
+    #[config(env = "BACKUP_DIR")]
+    pub dir: Option<String>,
+    /// Interval between backups in seconds, 0 disables periodic backups
+    #[config(env = "BACKUP_INTERVAL", default = 86400)]
+    pub interval: u64,
+    /// How many newest backups are kept, 0 keeps all
+    #[config(env = "BACKUP_KEEP", default = 7)]
+    pub keep: usize,
 }
 
 #[cfg(feature = "sqlite")]
@@ -93,10 +180,43 @@ impl Display for Conf {
         } else {
             f.pad("[empty]")?;
         }
+        f.pad("\n  server.admin_token: ")?;
+        if self
+            .server
+            .admin_token
+            .as_ref()
+            .is_none_or(|t| t.is_empty())
+        {
+            f.pad("[empty]")?;
+        } else {
+            f.pad("[redacted]")?;
+        }
         f.pad("\n  server.allow_new_register: ")?;
         self.server.allow_new_register.fmt(f)?;
+        f.pad("\n  server.separate_register: ")?;
+        self.server.separate_register.fmt(f)?;
+        f.pad("\n  server.ip_header: ")?;
+        if let Some(ip_header) = &self.server.ip_header {
+            f.pad(ip_header)?;
+        } else {
+            f.pad("[empty]")?;
+        }
         f.pad("\n  server.limits.json: ")?;
         self.server.limits.json.fmt(f)?;
+        f.pad("\n  server.limits.backup: ")?;
+        self.server.limits.backup.fmt(f)?;
+        f.pad("\n  server.rate_limit.enabled: ")?;
+        self.server.rate_limit.enabled.fmt(f)?;
+        f.pad("\n  server.rate_limit.window: ")?;
+        self.server.rate_limit.window.fmt(f)?;
+        f.pad("\n  server.rate_limit.max_per_email: ")?;
+        self.server.rate_limit.max_per_email.fmt(f)?;
+        f.pad("\n  server.rate_limit.max_per_ip: ")?;
+        self.server.rate_limit.max_per_ip.fmt(f)?;
+        f.pad("\n  server.rate_limit.lockout: ")?;
+        self.server.rate_limit.lockout.fmt(f)?;
+        f.pad("\n  server.rate_limit.max_lockout: ")?;
+        self.server.rate_limit.max_lockout.fmt(f)?;
 
         f.pad("\n  db.url: ")?;
         f.pad(&self.db.url())?;
@@ -109,6 +229,33 @@ impl Display for Conf {
         }
         f.pad("\n  jwt.issuer: ")?;
         f.pad(&self.jwt.issuer)?;
+        f.pad("\n  jwt.access_lifetime: ")?;
+        self.jwt.access_lifetime.fmt(f)?;
+        f.pad("\n  jwt.refresh_lifetime: ")?;
+        self.jwt.refresh_lifetime.fmt(f)?;
+
+        f.pad("\n  gc.enabled: ")?;
+        self.gc.enabled.fmt(f)?;
+        f.pad("\n  gc.retention: ")?;
+        self.gc.retention.fmt(f)?;
+        f.pad("\n  gc.interval: ")?;
+        self.gc.interval.fmt(f)?;
+
+        f.pad("\n  jobs.prune_sessions_interval: ")?;
+        self.jobs.prune_sessions_interval.fmt(f)?;
+        f.pad("\n  jobs.optimize_db_interval: ")?;
+        self.jobs.optimize_db_interval.fmt(f)?;
+
+        f.pad("\n  backup.dir: ")?;
+        if let Some(dir) = &self.backup.dir {
+            f.pad(dir)?;
+        } else {
+            f.pad("[empty]")?;
+        }
+        f.pad("\n  backup.interval: ")?;
+        self.backup.interval.fmt(f)?;
+        f.pad("\n  backup.keep: ")?;
+        self.backup.keep.fmt(f)?;
 
         Ok(())
     }
@@ -1,6 +1,11 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
 use anyhow::{Context, Result, anyhow};
+use diesel::connection::DefaultLoadingMode;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, Pool, PooledConnection};
+use rocket::serde::json::serde_json;
 
 #[cfg(feature = "sqlite")]
 use diesel::{prelude::SqliteConnection as DbConnection, sqlite::Sqlite as Backend};
@@ -10,10 +15,17 @@ use diesel::{mysql::Mysql as Backend, prelude::MysqlConnection as DbConnection};
 
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 
+use super::dump::{self, Header, Line};
 use crate::config::ConfDB;
-use crate::models::admin::DBStats;
-use crate::models::common::HistoryPackage;
-use crate::models::db::{History, MangaTags};
+use crate::current_timestamp;
+use crate::models::Versioned;
+use crate::models::admin::{DBStats, GCStats, User as AdminUser};
+use crate::models::common::{HistoryPackage, SessionID};
+use crate::models::db::{
+    History, Invite, InviteInsert, MangaTags, PasswordReset, PasswordResetInsert, RefreshToken,
+    RefreshTokenInsert, Session, SessionInsert,
+};
+use crate::models::request::MangaFilter;
 use crate::models::{
     common::{FavouritesPackage, Time, UserID},
     db::{Category, Favourite, Manga, Tag, User, UserInsert},
@@ -31,27 +43,60 @@ const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/mysql");
 #[cfg(all(feature = "original", not(test)))]
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/mysql-original");
 
+/// Max manga in one query for tags, each of them is a bind parameter
+const TAGS_QUERY_CHUNK: usize = 1000;
+
 type ConnManager = ConnectionManager<DbConnection>;
 type Conn = PooledConnection<ConnManager>;
 
+#[cfg(feature = "mysql")]
+diesel::define_sql_function! {
+    /// Id of row inserted by the last statement on the same connection
+    fn last_insert_id() -> diesel::sql_types::Unsigned<diesel::sql_types::BigInt>;
+}
+
+#[derive(Clone)]
 pub struct DB {
     conn: Pool<ConnManager>,
 }
 
 impl DB {
+    /// Connect and run pending migrations
     pub fn new(db_conf: ConfDB) -> Result<Self> {
-        let pool = Pool::builder()
-            .max_size(16)
-            .build(ConnectionManager::<DbConnection>::new(db_conf.url()))?;
-
-        let conn = &mut pool.get()?;
-        migrate(conn)?;
+        let db = Self::open(db_conf)?;
+        db.migrate()?;
 
         #[cfg(all(test, feature = "mysql"))]
-        conn.begin_test_transaction()?;
+        db.pool()?.begin_test_transaction()?;
+
+        Ok(db)
+    }
+    /// Connect without running migrations
+    pub fn open(db_conf: ConfDB) -> Result<Self> {
+        let builder = Pool::builder().max_size(16);
+
+        #[cfg(feature = "sqlite")]
+        let builder = builder.connection_customizer(Box::new(SqliteBusyTimeout));
 
+        let pool = builder.build(ConnectionManager::<DbConnection>::new(db_conf.url()))?;
         Ok(Self { conn: pool })
     }
+    /// Run pending migrations. Returns versions of applied ones
+    pub fn migrate(&self) -> Result<Vec<String>> {
+        let conn = &mut self.pool()?;
+        let applied = conn
+            .run_pending_migrations(MIGRATIONS)
+            .map_err(|e| anyhow!("failed to run migrations: {e}"))?;
+        Ok(applied.iter().map(|v| v.to_string()).collect())
+    }
+    /// Roll back the last applied migration. Returns its version
+    pub fn revert_migration(&self) -> Result<String> {
+        let conn = &mut self.pool()?;
+        let reverted = conn
+            .revert_last_migration(MIGRATIONS)
+            .map_err(|e| anyhow!("failed to revert migration: {e}"))?;
+        Ok(reverted.to_string())
+    }
     pub fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
         use super::schema::users::dsl::{email as user_email, users};
 
@@ -83,7 +128,44 @@ impl DB {
 
         self.get_user_by_email(email).map(|u| u.unwrap())
     }
-    #[cfg(feature = "migrate-md5")]
+    /// Create user, using invite code. Returns `None` if invite is unknown,
+    /// expired or has no uses left
+    pub fn create_user_with_invite(
+        &self,
+        email: &str,
+        password_hash: &str,
+        invite: &str,
+    ) -> Result<Option<User>> {
+        use super::schema::invites::dsl::{code, expires_at, invites, max_uses, uses};
+        use super::schema::users::dsl::{email as email_col, users};
+
+        let now = current_timestamp().unwrap_or_default();
+        let conn = &mut self.pool()?;
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            let updated = diesel::update(invites)
+                .filter(code.eq(invite))
+                .filter(uses.lt(max_uses))
+                .filter(expires_at.is_null().or(expires_at.gt(now)))
+                .set(uses.eq(uses + 1))
+                .execute(conn)?;
+            if updated == 0 {
+                return Ok(None);
+            }
+
+            diesel::insert_into(users)
+                .values(UserInsert {
+                    email: email.to_string(),
+                    password_hash: password_hash.to_string(),
+                })
+                .execute(conn)?;
+            Ok(Some(
+                users
+                    .filter(email_col.eq(email))
+                    .select(User::as_select())
+                    .first(conn)?,
+            ))
+        })
+    }
     pub fn update_user_password(&self, user_id: UserID, password_hash: &str) -> Result<()> {
         use super::schema::users::dsl::{id, password_hash as password_col, users};
 
@@ -93,33 +175,118 @@ impl DB {
             .execute(&mut self.pool()?)?;
         Ok(())
     }
-    pub fn set_favourites_synchronized(&self, user_id: UserID, time: Time) -> Result<()> {
-        use super::schema::users::dsl::{favourites_sync_timestamp, id, users};
+    /// Delete user with all related data
+    pub fn delete_user(&self, user_id: UserID) -> Result<()> {
+        use super::schema::{
+            categories, favourites, history, password_resets, refresh_tokens, sessions, users,
+        };
 
-        diesel::update(users)
-            .filter(id.eq(user_id))
-            .set(favourites_sync_timestamp.eq(time))
-            .execute(&mut self.pool()?)?;
-        Ok(())
+        // foreign keys are not enforced by SQLite by default, and favourites
+        // are not deleted on cascade, so delete everything explicitly
+        let conn = &mut self.pool()?;
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            let session_ids = sessions::table
+                .filter(sessions::user_id.eq(user_id))
+                .select(sessions::id);
+            diesel::delete(refresh_tokens::table)
+                .filter(refresh_tokens::session_id.eq_any(session_ids))
+                .execute(conn)?;
+            diesel::delete(sessions::table)
+                .filter(sessions::user_id.eq(user_id))
+                .execute(conn)?;
+            diesel::delete(password_resets::table)
+                .filter(password_resets::user_id.eq(user_id))
+                .execute(conn)?;
+            diesel::delete(favourites::table)
+                .filter(favourites::user_id.eq(user_id))
+                .execute(conn)?;
+            diesel::delete(history::table)
+                .filter(history::user_id.eq(user_id))
+                .execute(conn)?;
+            diesel::delete(categories::table)
+                .filter(categories::user_id.eq(user_id))
+                .execute(conn)?;
+            diesel::delete(users::table.find(user_id)).execute(conn)?;
+            Ok(())
+        })
     }
-    pub fn set_history_synchronized(&self, user_id: UserID, time: Time) -> Result<()> {
-        use super::schema::users::dsl::{history_sync_timestamp, id, users};
+    pub fn create_password_reset(&self, reset: PasswordResetInsert) -> Result<()> {
+        use super::schema::password_resets::dsl::password_resets;
 
-        diesel::update(users)
-            .filter(id.eq(user_id))
-            .set(history_sync_timestamp.eq(time))
+        diesel::insert_into(password_resets)
+            .values(reset)
             .execute(&mut self.pool()?)?;
         Ok(())
     }
-    pub fn add_favourites_package(&self, pkg: &FavouritesPackage, user_id: UserID) -> Result<()> {
+    /// Set new password using reset token and revoke all sessions of user.
+    /// Returns `None` if token is unknown, expired or already used
+    pub fn reset_password(&self, token_hash: &str, password_hash: &str) -> Result<Option<UserID>> {
+        use super::schema::password_resets::dsl::{
+            expires_at, password_resets, token_hash as token_hash_col, used_at,
+            user_id as reset_user_id,
+        };
+        use super::schema::sessions::dsl::{revoked_at, sessions, user_id as session_user_id};
+        use super::schema::users::dsl::{password_hash as password_col, users};
+
+        let now = current_timestamp().unwrap_or_default();
+        let conn = &mut self.pool()?;
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            let updated = diesel::update(password_resets)
+                .filter(token_hash_col.eq(token_hash))
+                .filter(used_at.is_null())
+                .filter(expires_at.gt(now))
+                .set(used_at.eq(now))
+                .execute(conn)?;
+            if updated == 0 {
+                return Ok(None);
+            }
+
+            let user_id: UserID = password_resets
+                .filter(token_hash_col.eq(token_hash))
+                .select(reset_user_id)
+                .first(conn)?;
+            diesel::update(users.find(user_id))
+                .set(password_col.eq(password_hash))
+                .execute(conn)?;
+            diesel::update(sessions)
+                .filter(session_user_id.eq(user_id))
+                .filter(revoked_at.is_null())
+                .set(revoked_at.eq(now))
+                .execute(conn)?;
+
+            Ok(Some(user_id))
+        })
+    }
+    /// Save favourites, marking rows as modified now, and update sync
+    /// timestamp. Returns `true` if favourites were synchronized by other
+    /// client after package `timestamp`. In `strict` mode nothing is saved then
+    pub fn add_favourites_package(
+        &self,
+        pkg: &FavouritesPackage,
+        user_id: UserID,
+        strict: bool,
+    ) -> Result<bool> {
+        use super::schema::users::dsl::{favourites_sync_timestamp, users};
+
         log::info!("adding favourites_package for user {user_id}");
 
         let conn = &mut self.pool()?;
 
         conn.transaction::<_, anyhow::Error, _>(|conn| {
+            Self::lock_user(conn, user_id)?;
+            let synchronized: Option<Time> = users
+                .find(user_id)
+                .select(favourites_sync_timestamp)
+                .first(conn)?;
+            let conflict = changed_since(synchronized, pkg.timestamp);
+            if conflict && strict {
+                return Ok(true);
+            }
+            let now = modification_time(synchronized);
+
             for c in &pkg.categories {
                 log::debug!("adding category {}", c.id);
-                Self::add_category(conn, c.to_db(user_id))?;
+                Self::add_category(conn, c.to_db(user_id, now))?;
             }
             for f in &pkg.favourites {
                 log::debug!("adding manga {}", f.manga.id);
@@ -131,15 +298,41 @@ impl DB {
                     f.manga_id,
                 )?;
                 log::debug!("adding favourite for manga {}", f.manga.id);
-                Self::add_favourite(conn, f.to_db(user_id))?;
+                Self::add_favourite(conn, f.to_db(user_id, now))?;
             }
-            Ok(())
-        })?;
-        Ok(())
+
+            // rows saved now are older than sync timestamp, so they are not
+            // sent back in delta sync
+            let synchronized = current_timestamp().unwrap_or_default().max(now + 1);
+            diesel::update(users.find(user_id))
+                .set(favourites_sync_timestamp.eq(synchronized))
+                .execute(conn)?;
+            Ok(conflict)
+        })
     }
-    pub fn add_history_package(&self, pkg: &HistoryPackage, user_id: UserID) -> Result<()> {
+    /// Save history, marking rows as modified now, and update sync timestamp.
+    /// Conflicts are handled like in `add_favourites_package`
+    pub fn add_history_package(
+        &self,
+        pkg: &HistoryPackage,
+        user_id: UserID,
+        strict: bool,
+    ) -> Result<bool> {
+        use super::schema::users::dsl::{history_sync_timestamp, users};
+
         let conn = &mut self.pool()?;
         conn.transaction::<_, anyhow::Error, _>(|conn| {
+            Self::lock_user(conn, user_id)?;
+            let synchronized: Option<Time> = users
+                .find(user_id)
+                .select(history_sync_timestamp)
+                .first(conn)?;
+            let conflict = changed_since(synchronized, pkg.timestamp);
+            if conflict && strict {
+                return Ok(true);
+            }
+            let now = modification_time(synchronized);
+
             for h in &pkg.history {
                 log::debug!("adding manga");
                 Self::add_manga(conn, h.manga.to_db())?;
@@ -150,73 +343,153 @@ impl DB {
                     h.manga_id,
                 )?;
                 log::debug!("adding history entry");
-                Self::add_history(conn, h.to_db(user_id))?;
+                Self::add_history(conn, h.to_db(user_id, now))?;
             }
-            Ok(())
-        })?;
+
+            // rows saved now are older than sync timestamp, so they are not
+            // sent back in delta sync
+            let synchronized = current_timestamp().unwrap_or_default().max(now + 1);
+            diesel::update(users.find(user_id))
+                .set(history_sync_timestamp.eq(synchronized))
+                .execute(conn)?;
+            Ok(conflict)
+        })
+    }
+    /// Lock row of user until the end of transaction, so saves of the same
+    /// user are serialized, and rows are marked as modified in commit order
+    fn lock_user(conn: &mut Conn, user_id: UserID) -> Result<()> {
+        use super::schema::users::dsl::{id, users};
+
+        #[cfg(feature = "mysql")]
+        users
+            .find(user_id)
+            .select(id)
+            .for_update()
+            .first::<UserID>(conn)?;
+
+        // SQLite has no row locks, but the first write takes lock of database
+        #[cfg(feature = "sqlite")]
+        diesel::update(users.find(user_id))
+            .set(id.eq(id))
+            .execute(conn)?;
+
         Ok(())
     }
-    pub fn load_favourites_package(&self, user_id: UserID) -> Result<FavouritesPackage> {
-        let categories = self.list_categories(user_id)?;
-        let favourites = self.list_favourites(user_id)?;
+    /// Load favourites. If `since` is set, only rows modified at or after it are
+    /// loaded
+    pub fn load_favourites_package(
+        &self,
+        user_id: UserID,
+        since: Option<Time>,
+    ) -> Result<FavouritesPackage> {
+        // read before rows: rows saved after it are marked as modified not
+        // earlier than it, so they are received in the next delta sync
+        let timestamp = self
+            .get_user(user_id)?
+            .and_then(|u| u.favourites_sync_timestamp);
+        let categories = self.list_categories(user_id, since)?;
+        let (favourites, manga): (Vec<_>, Vec<_>) =
+            self.list_favourites(user_id, since)?.into_iter().unzip();
+        let manga = self.manga_with_tags(manga)?;
 
         Ok(FavouritesPackage {
             categories: categories.iter().map(|c| c.to_api()).collect(),
             favourites: favourites
                 .iter()
-                .map(|(fav, manga)| -> Result<_> {
-                    // todo: maybe try to load manga with tags in one query
-                    // (it was hard to understand how to do it)
-                    // https://diesel.rs/guides/relations.html
-                    let tags: Vec<Tag> = self.list_tags(manga.id)?;
-                    Ok(fav.to_api(manga.to_api(tags.iter().map(|t| t.to_api()).collect())))
+                .filter_map(|fav| {
+                    let (manga, tags) = manga.get(&fav.manga_id)?;
+                    Some(fav.to_api(manga.to_api(tags.iter().map(|t| t.to_api()).collect())))
                 })
-                .collect::<Result<Vec<_>>>()?,
-            timestamp: self
-                .get_user(user_id)?
-                .and_then(|u| u.favourites_sync_timestamp),
+                .collect(),
+            timestamp,
         })
     }
-    pub fn load_history_package(&self, user_id: UserID) -> Result<HistoryPackage> {
-        let history = self.list_history(user_id)?;
+    /// Load history. If `since` is set, only rows modified at or after it are
+    /// loaded
+    pub fn load_history_package(
+        &self,
+        user_id: UserID,
+        since: Option<Time>,
+    ) -> Result<HistoryPackage> {
+        // read before rows, like in `load_favourites_package`
+        let timestamp = self
+            .get_user(user_id)?
+            .and_then(|u| u.history_sync_timestamp);
+        let (history, manga): (Vec<_>, Vec<_>) =
+            self.list_history(user_id, since)?.into_iter().unzip();
+        let manga = self.manga_with_tags(manga)?;
 
         Ok(HistoryPackage {
             history: history
                 .iter()
-                .map(|(hist, manga)| -> Result<_> {
-                    // todo: same as in load_favourites_package
-                    let tags: Vec<Tag> = self.list_tags(manga.id)?;
-                    Ok(hist.to_api(manga.to_api(tags.iter().map(|t| t.to_api()).collect())))
+                .filter_map(|hist| {
+                    let (manga, tags) = manga.get(&hist.manga_id)?;
+                    Some(hist.to_api(manga.to_api(tags.iter().map(|t| t.to_api()).collect())))
                 })
-                .collect::<Result<Vec<_>>>()?,
-            timestamp: self
-                .get_user(user_id)?
-                .and_then(|u| u.history_sync_timestamp),
+                .collect(),
+            timestamp,
         })
     }
-    fn list_favourites(&self, user_id: UserID) -> Result<Vec<(Favourite, Manga)>> {
-        use super::schema::favourites::dsl::user_id as user_id_col;
+    fn list_favourites(
+        &self,
+        user_id: UserID,
+        since: Option<Time>,
+    ) -> Result<Vec<(Favourite, Manga)>> {
+        use super::schema::favourites::dsl::{modified_at, user_id as user_id_col};
         use super::schema::{favourites, manga};
 
         Ok(favourites::table
             .inner_join(manga::table)
             .filter(user_id_col.eq(user_id))
+            .filter(modified_at.ge(since.unwrap_or(Time::MIN)))
             .select((Favourite::as_select(), Manga::as_select()))
             .load(&mut self.pool()?)?)
     }
-    fn list_history(&self, user_id: UserID) -> Result<Vec<(History, Manga)>> {
-        use super::schema::history::dsl::user_id as user_id_col;
+    fn list_history(&self, user_id: UserID, since: Option<Time>) -> Result<Vec<(History, Manga)>> {
+        use super::schema::history::dsl::{modified_at, user_id as user_id_col};
         use super::schema::{history, manga};
 
         Ok(history::table
             .inner_join(manga::table)
             .filter(user_id_col.eq(user_id))
+            .filter(modified_at.ge(since.unwrap_or(Time::MIN)))
             .select((History::as_select(), Manga::as_select()))
             .load(&mut self.pool()?)?)
     }
+    /// Whether stored item is newer than saved one, so it must be kept. Kept
+    /// item is marked as modified, so it is sent back to client in delta sync
+    fn keep_stored<T: Versioned>(
+        conn: &mut Conn,
+        stored: Option<T>,
+        item: &T,
+        mark_modified: impl FnOnce(&mut Conn) -> QueryResult<usize>,
+    ) -> Result<bool> {
+        if stored.is_none_or(|s| s.version() <= item.version()) {
+            return Ok(false);
+        }
+        mark_modified(conn)?;
+        Ok(true)
+    }
+    /// Upsert category, unless stored one is newer
     fn add_category(conn: &mut Conn, category: Category) -> Result<()> {
         #[allow(unused)]
-        use super::schema::categories::dsl::{categories, id, user_id};
+        use super::schema::categories::dsl::{categories, id, modified_at, user_id};
+
+        let key = (category.id, category.user_id);
+        let stored = categories
+            .find(key)
+            .select(Category::as_select())
+            .first(conn)
+            .optional()?;
+        let kept = Self::keep_stored(conn, stored, &category, |conn| {
+            diesel::update(categories.find(key))
+                .set(modified_at.eq(category.modified_at))
+                .execute(conn)
+        })?;
+        if kept {
+            log::debug!("keeping newer category {}", category.id);
+            return Ok(());
+        }
 
         let q = diesel::insert_into(categories).values(&category);
 
@@ -230,11 +503,12 @@ impl DB {
 
         Ok(())
     }
-    fn list_categories(&self, user_id: UserID) -> Result<Vec<Category>> {
-        use super::schema::categories::dsl::{categories, user_id as user_id_col};
+    fn list_categories(&self, user_id: UserID, since: Option<Time>) -> Result<Vec<Category>> {
+        use super::schema::categories::dsl::{categories, modified_at, user_id as user_id_col};
 
         Ok(categories
             .filter(user_id_col.eq(user_id))
+            .filter(modified_at.ge(since.unwrap_or(Time::MIN)))
             .select(Category::as_select())
             .get_results(&mut self.pool()?)?)
     }
@@ -251,11 +525,90 @@ impl DB {
         let q = q.on_conflict((id,));
 
         q.do_update().set(&manga).execute(conn)?;
+
+        #[cfg(feature = "sqlite")]
+        Self::index_manga(conn, &manga)?;
         Ok(())
     }
+    /// Update manga in full-text index. In MySQL it is updated automatically
+    #[cfg(feature = "sqlite")]
+    fn index_manga(conn: &mut Conn, manga: &Manga) -> Result<()> {
+        use diesel::sql_types::{BigInt, Nullable, Text};
+
+        diesel::sql_query("delete from manga_fts where rowid = ?")
+            .bind::<BigInt, _>(manga.id)
+            .execute(conn)?;
+        diesel::sql_query("insert into manga_fts (rowid, title, alt_title) values (?, ?, ?)")
+            .bind::<BigInt, _>(manga.id)
+            .bind::<Text, _>(&manga.title)
+            .bind::<Nullable<Text>, _>(&manga.alt_title)
+            .execute(conn)?;
+        Ok(())
+    }
+    /// Full-text search by title and alt title, best matches first
+    pub fn search_manga(&self, query: &str, limit: usize) -> Result<Vec<(Manga, Vec<Tag>)>> {
+        use diesel::sql_types::{BigInt, Text};
+
+        #[cfg(feature = "sqlite")]
+        let q = {
+            // every word is quoted, so query syntax can't be used, and the
+            // last one is prefix
+            let words: Vec<_> = query
+                .split_whitespace()
+                .map(|w| format!("\"{}\"", w.replace('"', "\"\"")))
+                .collect();
+            if words.is_empty() {
+                return Ok(vec![]);
+            }
+            diesel::sql_query(
+                "select manga.* from manga_fts \
+                join manga on manga.id = manga_fts.rowid \
+                where manga_fts match ? \
+                order by manga_fts.rank \
+                limit ?",
+            )
+            .bind::<Text, _>(format!("{}*", words.join(" ")))
+            .bind::<BigInt, _>(limit as i64)
+        };
+
+        #[cfg(feature = "mysql")]
+        let q = diesel::sql_query(
+            "select * from manga \
+            where match (title, alt_title) against (?) \
+            order by match (title, alt_title) against (?) desc \
+            limit ?",
+        )
+        .bind::<Text, _>(query)
+        .bind::<Text, _>(query)
+        .bind::<BigInt, _>(limit as i64);
+
+        let conn = &mut self.pool()?;
+        let manga_list: Vec<Manga> = q.load(conn)?;
+        let tags = Self::list_manga_tags(conn, &manga_list)?;
+        Ok(manga_list.into_iter().zip(tags).collect())
+    }
+    /// Upsert history entry, unless stored one is newer
     fn add_history(conn: &mut Conn, history: History) -> Result<()> {
         #[allow(unused)]
-        use super::schema::history::dsl::{history as history_table, manga_id, user_id};
+        use super::schema::history::dsl::{
+            history as history_table, manga_id, modified_at, user_id,
+        };
+
+        let key = (history.manga_id, history.user_id);
+        let stored = history_table
+            .find(key)
+            .select(History::as_select())
+            .first(conn)
+            .optional()?;
+        let kept = Self::keep_stored(conn, stored, &history, |conn| {
+            diesel::update(history_table.find(key))
+                .set(modified_at.eq(history.modified_at))
+                .execute(conn)
+        })?;
+        if kept {
+            log::debug!("keeping newer history for manga {}", history.manga_id);
+            return Ok(());
+        }
 
         let q = diesel::insert_into(history_table).values(&history);
 
@@ -279,34 +632,163 @@ impl DB {
             return Ok(None);
         };
 
-        let tags = self.list_tags(manga.id)?;
+        let tags = Self::list_manga_tags(&mut self.pool()?, std::slice::from_ref(&manga))?
+            .pop()
+            .unwrap_or_default();
         Ok(Some((manga, tags)))
     }
-    pub fn list_manga(&self, offset: usize, limit: usize) -> Result<Vec<(Manga, Vec<Tag>)>> {
-        use super::schema::{manga, tags};
+    /// List manga ordered by id
+    pub fn list_manga(
+        &self,
+        offset: usize,
+        limit: usize,
+        filter: &MangaFilter,
+    ) -> Result<Vec<(Manga, Vec<Tag>)>> {
+        use super::schema::manga;
 
         let conn = &mut self.pool()?;
-        let all_manga = manga::table
+        let manga_list = Self::filter_manga(filter)
             .select(Manga::as_select())
+            .order(manga::id)
             .offset(offset as i64)
             .limit(limit as i64)
             .load(conn)?;
-        let manga_list = manga::table.select(Manga::as_select()).load(conn)?;
 
-        let all_manga_tags = MangaTags::belonging_to(&manga_list)
-            .inner_join(tags::table)
-            .select((MangaTags::as_select(), Tag::as_select()))
+        let tags = Self::list_manga_tags(conn, &manga_list)?;
+        Ok(manga_list.into_iter().zip(tags).collect())
+    }
+    /// List manga ordered by id, which are after manga with id `after`, or
+    /// from the start
+    pub fn list_manga_after(
+        &self,
+        after: Option<i64>,
+        limit: usize,
+        filter: &MangaFilter,
+    ) -> Result<Vec<(Manga, Vec<Tag>)>> {
+        use super::schema::manga;
+
+        let conn = &mut self.pool()?;
+        let mut q = Self::filter_manga(filter)
+            .select(Manga::as_select())
+            .order(manga::id)
+            .limit(limit as i64);
+        if let Some(after) = after {
+            q = q.filter(manga::id.gt(after));
+        }
+        let manga_list = q.load(conn)?;
+
+        let tags = Self::list_manga_tags(conn, &manga_list)?;
+        Ok(manga_list.into_iter().zip(tags).collect())
+    }
+    fn filter_manga(filter: &MangaFilter) -> super::schema::manga::BoxedQuery<'_, Backend> {
+        use super::schema::{manga, manga_tags, tags};
+
+        let mut q = manga::table.into_boxed();
+        if let Some(search) = &filter.q {
+            // escaping wildcards, so search is literal
+            let pattern = format!(
+                "%{}%",
+                search
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            );
+            q = q.filter(
+                manga::title
+                    .like(pattern.clone())
+                    .escape('\\')
+                    .or(manga::alt_title.like(pattern).escape('\\')),
+            );
+        }
+        if let Some(source) = &filter.source {
+            q = q.filter(manga::source.eq(source));
+        }
+        if let Some(state) = &filter.state {
+            q = q.filter(manga::state.eq(state));
+        }
+        if let Some(content_rating) = &filter.content_rating {
+            q = q.filter(manga::content_rating.eq(content_rating));
+        }
+        if let Some(author) = &filter.author {
+            q = q.filter(manga::author.eq(author));
+        }
+        if let Some(tag) = &filter.tag {
+            q = q.filter(
+                manga::id.eq_any(
+                    manga_tags::table
+                        .inner_join(tags::table)
+                        .filter(tags::key.eq(tag))
+                        .select(manga_tags::manga_id),
+                ),
+            );
+        }
+        q
+    }
+    /// List tags ordered by id
+    pub fn list_tags(&self, source: Option<&str>, offset: usize, limit: usize) -> Result<Vec<Tag>> {
+        use super::schema::tags;
+
+        let mut q = tags::table
+            .select(Tag::as_select())
+            .order(tags::id)
+            .offset(offset as i64)
+            .limit(limit as i64)
+            .into_boxed();
+        if let Some(source) = source {
+            q = q.filter(tags::source.eq(source));
+        }
+        Ok(q.load(&mut self.pool()?)?)
+    }
+    pub fn get_tag(&self, tag_id: i64) -> Result<Option<Tag>> {
+        use super::schema::tags;
+
+        Ok(tags::table
+            .find(tag_id)
+            .select(Tag::as_select())
+            .first(&mut self.pool()?)
+            .optional()?)
+    }
+    /// List manga with tag, ordered by id
+    pub fn list_tag_manga(
+        &self,
+        tag_id: i64,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<(Manga, Vec<Tag>)>> {
+        use super::schema::{manga, manga_tags};
+
+        let conn = &mut self.pool()?;
+        let manga_list = manga::table
+            .inner_join(manga_tags::table)
+            .filter(manga_tags::tag_id.eq(tag_id))
+            .select(Manga::as_select())
+            .order(manga::id)
+            .offset(offset as i64)
+            .limit(limit as i64)
             .load(conn)?;
 
-        Ok(all_manga_tags
-            .grouped_by(&manga_list)
-            .into_iter()
-            .zip(all_manga)
-            .map(|(tag, m)| (m, tag.into_iter().map(|(_, t)| t).collect()))
-            .collect())
+        let tags = Self::list_manga_tags(conn, &manga_list)?;
+        Ok(manga_list.into_iter().zip(tags).collect())
     }
+    /// Upsert favourite, unless stored one is newer
     fn add_favourite(conn: &mut Conn, favourite: Favourite) -> Result<()> {
-        use super::schema::favourites::dsl::favourites;
+        use super::schema::favourites::dsl::{favourites, modified_at};
+
+        let key = (favourite.manga_id, favourite.category_id, favourite.user_id);
+        let stored = favourites
+            .find(key)
+            .select(Favourite::as_select())
+            .first(conn)
+            .optional()?;
+        let kept = Self::keep_stored(conn, stored, &favourite, |conn| {
+            diesel::update(favourites.find(key))
+                .set(modified_at.eq(favourite.modified_at))
+                .execute(conn)
+        })?;
+        if kept {
+            log::debug!("keeping newer favourite for manga {}", favourite.manga_id);
+            return Ok(());
+        }
 
         diesel::replace_into(favourites)
             .values(vec![favourite])
@@ -337,27 +819,301 @@ impl DB {
 
         Ok(())
     }
-    fn list_tags(&self, manga_id: i64) -> Result<Vec<Tag>> {
-        use super::schema::{
-            manga_tags::dsl::{manga_id as manga_id_col, manga_tags, tag_id as manga_tag_id_col},
-            tags::dsl::{id as tags_id_col, tags},
-        };
+    /// Load tags for manga list, in the same order. Tags are loaded for all
+    /// manga at once, instead of querying them for each manga, so ids in the
+    /// list should be unique
+    fn list_manga_tags(conn: &mut Conn, manga_list: &[Manga]) -> Result<Vec<Vec<Tag>>> {
+        use super::schema::tags;
 
-        let conn = &mut self.pool()?;
-        let tag_ids: Vec<i64> = manga_tags
-            .filter(manga_id_col.eq(manga_id))
-            .select(manga_tag_id_col)
-            .load(conn)?;
-        Ok(tags
-            .filter(tags_id_col.eq_any(tag_ids))
-            .select(Tag::as_select())
-            .load(conn)?)
+        let mut result = Vec::with_capacity(manga_list.len());
+        // keeping number of bind parameters under database limit
+        for chunk in manga_list.chunks(TAGS_QUERY_CHUNK) {
+            let manga_tags = MangaTags::belonging_to(chunk)
+                .inner_join(tags::table)
+                .select((MangaTags::as_select(), Tag::as_select()))
+                .load(conn)?;
+            result.extend(
+                manga_tags
+                    .grouped_by(chunk)
+                    .into_iter()
+                    .map(|tags| tags.into_iter().map(|(_, t)| t).collect()),
+            );
+        }
+        Ok(result)
+    }
+    /// Deduplicate manga from joined rows and load their tags
+    fn manga_with_tags(&self, mut manga: Vec<Manga>) -> Result<HashMap<i64, (Manga, Vec<Tag>)>> {
+        manga.sort_unstable_by_key(|m| m.id);
+        manga.dedup_by_key(|m| m.id);
+        let tags = Self::list_manga_tags(&mut self.pool()?, &manga)?;
+        Ok(manga
+            .into_iter()
+            .zip(tags)
+            .map(|(m, tags)| (m.id, (m, tags)))
+            .collect())
     }
     fn pool(&self) -> Result<Conn> {
         self.conn.get().context("cannot get db.pool")
     }
 }
 
+// sessions
+impl DB {
+    /// Create new session with initial refresh token
+    pub fn create_session(
+        &self,
+        session: SessionInsert,
+        token_hash: &str,
+        expires_at: Time,
+    ) -> Result<SessionID> {
+        use super::schema::refresh_tokens::dsl::refresh_tokens;
+        #[allow(unused)]
+        use super::schema::sessions::dsl::{id, sessions};
+
+        let conn = &mut self.pool()?;
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            #[cfg(feature = "sqlite")]
+            let session_id = diesel::insert_into(sessions)
+                .values(&session)
+                .returning(id)
+                .get_result(conn)?;
+
+            // id is taken from the same connection, so concurrent logins of
+            // the same user don't get each other's session
+            #[cfg(feature = "mysql")]
+            let session_id = {
+                diesel::insert_into(sessions)
+                    .values(&session)
+                    .execute(conn)?;
+                diesel::select(last_insert_id()).get_result::<u64>(conn)? as SessionID
+            };
+
+            diesel::insert_into(refresh_tokens)
+                .values(RefreshTokenInsert {
+                    session_id,
+                    token_hash: token_hash.to_string(),
+                    expires_at,
+                })
+                .execute(conn)?;
+
+            Ok(session_id)
+        })
+    }
+    pub fn is_session_active(&self, session_id: SessionID, user_id: UserID) -> Result<bool> {
+        use super::schema::sessions::dsl::{revoked_at, sessions, user_id as user_id_col};
+
+        let count: i64 = sessions
+            .find(session_id)
+            .filter(user_id_col.eq(user_id))
+            .filter(revoked_at.is_null())
+            .count()
+            .get_result(&mut self.pool()?)?;
+        Ok(count > 0)
+    }
+    /// Update last seen time of session. To not write on every request, time
+    /// is updated only if it is older than a minute
+    pub fn touch_session(&self, session_id: SessionID) -> Result<()> {
+        use super::schema::sessions::dsl::{id, last_seen_at, sessions};
+
+        const THRESHOLD_MS: Time = 60 * 1000;
+
+        let now = current_timestamp().unwrap_or_default();
+        diesel::update(sessions)
+            .filter(id.eq(session_id))
+            .filter(last_seen_at.lt(now - THRESHOLD_MS))
+            .set(last_seen_at.eq(now))
+            .execute(&mut self.pool()?)?;
+        Ok(())
+    }
+    /// List sessions, which are not revoked
+    pub fn list_sessions(&self, user_id: UserID) -> Result<Vec<Session>> {
+        use super::schema::sessions::dsl::{
+            last_seen_at, revoked_at, sessions, user_id as user_id_col,
+        };
+
+        Ok(sessions
+            .filter(user_id_col.eq(user_id))
+            .filter(revoked_at.is_null())
+            .order(last_seen_at.desc())
+            .select(Session::as_select())
+            .load(&mut self.pool()?)?)
+    }
+    /// Revoke session of user. Returns `false` if user has no such active
+    /// session
+    pub fn revoke_session(&self, user_id: UserID, session_id: SessionID) -> Result<bool> {
+        use super::schema::sessions::dsl::{id, revoked_at, sessions, user_id as user_id_col};
+
+        let updated = diesel::update(sessions)
+            .filter(id.eq(session_id))
+            .filter(user_id_col.eq(user_id))
+            .filter(revoked_at.is_null())
+            .set(revoked_at.eq(current_timestamp().unwrap_or_default()))
+            .execute(&mut self.pool()?)?;
+        Ok(updated > 0)
+    }
+    /// Replace refresh token with a new one. Returns session, to which token
+    /// belongs
+    ///
+    /// Returns `None` if token is unknown, expired or session is revoked. If
+    /// token was already used, the whole session is revoked, because token was
+    /// probably stolen
+    pub fn rotate_refresh_token(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+        expires_at: Time,
+    ) -> Result<Option<Session>> {
+        use super::schema::refresh_tokens::dsl::{
+            refresh_tokens, token_hash as token_hash_col, used_at,
+        };
+
+        let now = current_timestamp().unwrap_or_default();
+        let conn = &mut self.pool()?;
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            let Some(token) = Self::find_refresh_token(conn, token_hash)? else {
+                return Ok(None);
+            };
+            let session = Self::find_session(conn, token.session_id)?;
+            if session.revoked_at.is_some() {
+                return Ok(None);
+            }
+            if token.used_at.is_some() {
+                log::warn!(
+                    "reuse of refresh token detected, revoking session {}",
+                    session.id
+                );
+                Self::revoke_session_conn(conn, session.id, now)?;
+                return Ok(None);
+            }
+            if token.expires_at < now {
+                return Ok(None);
+            }
+
+            diesel::update(refresh_tokens)
+                .filter(token_hash_col.eq(token_hash))
+                .set(used_at.eq(now))
+                .execute(conn)?;
+            Self::touch_session_conn(conn, session.id, now)?;
+            diesel::insert_into(refresh_tokens)
+                .values(RefreshTokenInsert {
+                    session_id: session.id,
+                    token_hash: new_token_hash.to_string(),
+                    expires_at,
+                })
+                .execute(conn)?;
+
+            Ok(Some(session))
+        })
+    }
+    /// Revoke session, to which refresh token belongs. Returns `false` if
+    /// token is unknown
+    pub fn revoke_session_by_refresh_token(&self, token_hash: &str) -> Result<bool> {
+        let conn = &mut self.pool()?;
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            let Some(token) = Self::find_refresh_token(conn, token_hash)? else {
+                return Ok(false);
+            };
+            Self::revoke_session_conn(
+                conn,
+                token.session_id,
+                current_timestamp().unwrap_or_default(),
+            )?;
+            Ok(true)
+        })
+    }
+    /// Revoke all sessions of user, except one
+    pub fn revoke_other_sessions(&self, user_id: UserID, keep: SessionID) -> Result<()> {
+        use super::schema::sessions::dsl::{id, revoked_at, sessions, user_id as user_id_col};
+
+        diesel::update(sessions)
+            .filter(user_id_col.eq(user_id))
+            .filter(id.ne(keep))
+            .filter(revoked_at.is_null())
+            .set(revoked_at.eq(current_timestamp().unwrap_or_default()))
+            .execute(&mut self.pool()?)?;
+        Ok(())
+    }
+    /// Revoke all sessions of user
+    pub fn revoke_sessions(&self, user_id: UserID) -> Result<()> {
+        use super::schema::sessions::dsl::{revoked_at, sessions, user_id as user_id_col};
+
+        diesel::update(sessions)
+            .filter(user_id_col.eq(user_id))
+            .filter(revoked_at.is_null())
+            .set(revoked_at.eq(current_timestamp().unwrap_or_default()))
+            .execute(&mut self.pool()?)?;
+        Ok(())
+    }
+    /// Delete expired refresh tokens, revoked sessions and sessions, which
+    /// can't be used anymore: without valid refresh tokens and not seen since
+    /// `seen_before`. Returns number of deleted sessions
+    pub fn prune_sessions(&self, seen_before: Time) -> Result<usize> {
+        use super::schema::{refresh_tokens, sessions};
+        use diesel::dsl::{exists, not};
+
+        let now = current_timestamp().unwrap_or_default();
+        let conn = &mut self.pool()?;
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            diesel::delete(refresh_tokens::table)
+                .filter(refresh_tokens::expires_at.lt(now))
+                .execute(conn)?;
+
+            let has_tokens =
+                exists(refresh_tokens::table.filter(refresh_tokens::session_id.eq(sessions::id)));
+            let stale = sessions::table
+                .filter(
+                    sessions::revoked_at
+                        .is_not_null()
+                        .or(not(has_tokens).and(sessions::last_seen_at.lt(seen_before))),
+                )
+                .select(sessions::id)
+                .load::<SessionID>(conn)?;
+            diesel::delete(refresh_tokens::table)
+                .filter(refresh_tokens::session_id.eq_any(&stale))
+                .execute(conn)?;
+            Ok(diesel::delete(sessions::table)
+                .filter(sessions::id.eq_any(&stale))
+                .execute(conn)?)
+        })
+    }
+    fn find_refresh_token(conn: &mut Conn, token_hash: &str) -> Result<Option<RefreshToken>> {
+        use super::schema::refresh_tokens::dsl::{refresh_tokens, token_hash as token_hash_col};
+
+        Ok(refresh_tokens
+            .filter(token_hash_col.eq(token_hash))
+            .select(RefreshToken::as_select())
+            .first(conn)
+            .optional()?)
+    }
+    fn find_session(conn: &mut Conn, session_id: SessionID) -> Result<Session> {
+        use super::schema::sessions::dsl::sessions;
+
+        Ok(sessions
+            .find(session_id)
+            .select(Session::as_select())
+            .first(conn)?)
+    }
+    fn touch_session_conn(conn: &mut Conn, session_id: SessionID, time: Time) -> Result<()> {
+        use super::schema::sessions::dsl::{id, last_seen_at, sessions};
+
+        diesel::update(sessions)
+            .filter(id.eq(session_id))
+            .set(last_seen_at.eq(time))
+            .execute(conn)?;
+        Ok(())
+    }
+    fn revoke_session_conn(conn: &mut Conn, session_id: SessionID, time: Time) -> Result<()> {
+        use super::schema::sessions::dsl::{id, revoked_at, sessions};
+
+        diesel::update(sessions)
+            .filter(id.eq(session_id))
+            .filter(revoked_at.is_null())
+            .set(revoked_at.eq(time))
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
 // admin
 impl DB {
     pub fn stats(&self) -> Result<DBStats> {
@@ -372,10 +1128,365 @@ impl DB {
             manga_count: manga_count as u64,
         })
     }
+    pub fn create_invite(&self, invite: InviteInsert) -> Result<Invite> {
+        use super::schema::invites::dsl::{code, invites};
+
+        let conn = &mut self.pool()?;
+        diesel::insert_into(invites).values(&invite).execute(conn)?;
+        Ok(invites
+            .filter(code.eq(&invite.code))
+            .select(Invite::as_select())
+            .first(conn)?)
+    }
+    pub fn list_invites(&self) -> Result<Vec<Invite>> {
+        use super::schema::invites::dsl::{id, invites};
+
+        Ok(invites
+            .order(id.asc())
+            .select(Invite::as_select())
+            .load(&mut self.pool()?)?)
+    }
+    /// Reclaim free space and update statistics for query planner
+    pub fn optimize(&self) -> Result<()> {
+        use diesel::connection::SimpleConnection;
+
+        let conn = &mut self.pool()?;
+
+        #[cfg(feature = "sqlite")]
+        conn.batch_execute("VACUUM; ANALYZE;")?;
+
+        #[cfg(feature = "mysql")]
+        conn.batch_execute(
+            "OPTIMIZE TABLE categories, favourites, history, manga, manga_tags, tags;",
+        )?;
+
+        Ok(())
+    }
+    /// Write consistent copy of database into new file, while database is in
+    /// use
+    #[cfg(feature = "sqlite")]
+    pub fn backup_into(&self, path: &str) -> Result<()> {
+        diesel::sql_query("VACUUM INTO ?")
+            .bind::<diesel::sql_types::Text, _>(path)
+            .execute(&mut self.pool()?)?;
+        Ok(())
+    }
+    #[cfg(feature = "mysql")]
+    pub fn backup_into(&self, _path: &str) -> Result<()> {
+        Err(anyhow!("backups are supported only for SQLite"))
+    }
+    /// Hard-delete rows, which were soft-deleted before `deleted_before`, and
+    /// manga with tags, which are not referenced by anyone
+    pub fn gc(&self, deleted_before: Time) -> Result<GCStats> {
+        use super::schema::{categories, favourites, history, manga, manga_tags, tags};
+        use diesel::dsl::{exists, not};
+
+        let conn = &mut self.pool()?;
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            let favourites_count = diesel::delete(favourites::table)
+                .filter(favourites::deleted_at.gt(0))
+                .filter(favourites::deleted_at.lt(deleted_before))
+                .execute(conn)?;
+            // after favourites, which may reference category. Referenced one
+            // is kept, it can't be deleted in MySQL
+            let categories_count = diesel::delete(categories::table)
+                .filter(categories::deleted_at.gt(0))
+                .filter(categories::deleted_at.lt(deleted_before))
+                .filter(not(exists(
+                    favourites::table
+                        .filter(favourites::category_id.eq(categories::id))
+                        .filter(favourites::user_id.eq(categories::user_id)),
+                )))
+                .execute(conn)?;
+            let history_count = diesel::delete(history::table)
+                .filter(history::deleted_at.gt(0))
+                .filter(history::deleted_at.lt(deleted_before))
+                .execute(conn)?;
+
+            let manga_count = diesel::delete(manga::table)
+                .filter(not(exists(
+                    favourites::table.filter(favourites::manga_id.eq(manga::id)),
+                )))
+                .filter(not(exists(
+                    history::table.filter(history::manga_id.eq(manga::id)),
+                )))
+                .execute(conn)?;
+            #[cfg(feature = "sqlite")]
+            diesel::sql_query("delete from manga_fts where rowid not in (select id from manga)")
+                .execute(conn)?;
+            // not deleted on cascade by SQLite
+            diesel::delete(manga_tags::table)
+                .filter(not(exists(
+                    manga::table.filter(manga::id.eq(manga_tags::manga_id)),
+                )))
+                .execute(conn)?;
+            let tags_count = diesel::delete(tags::table)
+                .filter(not(exists(
+                    manga_tags::table.filter(manga_tags::tag_id.eq(tags::id)),
+                )))
+                .execute(conn)?;
+
+            Ok(GCStats {
+                favourites: favourites_count as u64,
+                categories: categories_count as u64,
+                history: history_count as u64,
+                manga: manga_count as u64,
+                tags: tags_count as u64,
+            })
+        })
+    }
+    pub fn list_users(&self, offset: usize, limit: usize) -> Result<Vec<AdminUser>> {
+        use super::schema::users::dsl::{id, users};
+
+        let conn = &mut self.pool()?;
+        let list = users
+            .order(id.asc())
+            .offset(offset as i64)
+            .limit(limit as i64)
+            .select(User::as_select())
+            .load(conn)?;
+        Self::with_counts(conn, list)
+    }
+    pub fn get_user_info(&self, user_id: UserID) -> Result<Option<AdminUser>> {
+        use super::schema::users::dsl::users;
+
+        let conn = &mut self.pool()?;
+        let user = users
+            .find(user_id)
+            .select(User::as_select())
+            .first(conn)
+            .optional()?;
+        Ok(Self::with_counts(conn, user.into_iter().collect())?.pop())
+    }
+    /// Disable or enable user. Sessions of disabled user are revoked.
+    /// Returns `false` if user doesn't exist
+    pub fn set_user_disabled(&self, user_id: UserID, disabled: bool) -> Result<bool> {
+        use super::schema::sessions::dsl::{revoked_at, sessions, user_id as session_user_id};
+        use super::schema::users::dsl::{disabled_at, users};
+
+        let now = current_timestamp().unwrap_or_default();
+        let conn = &mut self.pool()?;
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            if users.find(user_id).count().get_result::<i64>(conn)? == 0 {
+                return Ok(false);
+            }
+            if !disabled {
+                diesel::update(users.find(user_id))
+                    .set(disabled_at.eq(None::<Time>))
+                    .execute(conn)?;
+                return Ok(true);
+            }
+
+            // keep original time, if user is already disabled
+            diesel::update(users.find(user_id))
+                .filter(disabled_at.is_null())
+                .set(disabled_at.eq(now))
+                .execute(conn)?;
+            diesel::update(sessions)
+                .filter(session_user_id.eq(user_id))
+                .filter(revoked_at.is_null())
+                .set(revoked_at.eq(now))
+                .execute(conn)?;
+            Ok(true)
+        })
+    }
+    /// Add counts of not deleted items to users
+    fn with_counts(conn: &mut Conn, list: Vec<User>) -> Result<Vec<AdminUser>> {
+        use super::schema::{categories, favourites, history};
+        use diesel::dsl::count_star;
+
+        let ids: Vec<UserID> = list.iter().map(|u| u.id).collect();
+        let favourites: HashMap<UserID, i64> = favourites::table
+            .filter(favourites::user_id.eq_any(&ids))
+            .filter(favourites::deleted_at.eq(0))
+            .group_by(favourites::user_id)
+            .select((favourites::user_id, count_star()))
+            .load::<(UserID, i64)>(conn)?
+            .into_iter()
+            .collect();
+        let history: HashMap<UserID, i64> = history::table
+            .filter(history::user_id.eq_any(&ids))
+            .filter(history::deleted_at.eq(0))
+            .group_by(history::user_id)
+            .select((history::user_id, count_star()))
+            .load::<(UserID, i64)>(conn)?
+            .into_iter()
+            .collect();
+        let categories: HashMap<UserID, i64> = categories::table
+            .filter(categories::user_id.eq_any(&ids))
+            .filter(categories::deleted_at.eq(0))
+            .group_by(categories::user_id)
+            .select((categories::user_id, count_star()))
+            .load::<(UserID, i64)>(conn)?
+            .into_iter()
+            .collect();
+
+        Ok(list
+            .into_iter()
+            .map(|u| {
+                let count =
+                    |map: &HashMap<UserID, i64>| map.get(&u.id).copied().unwrap_or(0) as u64;
+                AdminUser {
+                    favourites_count: count(&favourites),
+                    history_count: count(&history),
+                    categories_count: count(&categories),
+                    id: u.id,
+                    email: u.email,
+                    nickname: u.nickname,
+                    favourites_sync_timestamp: u.favourites_sync_timestamp,
+                    history_sync_timestamp: u.history_sync_timestamp,
+                    disabled_at: u.disabled_at,
+                }
+            })
+            .collect())
+    }
+}
+
+// dump
+impl DB {
+    /// Write all tables as JSON lines. Returns number of rows
+    pub fn dump(&self, mut out: impl Write) -> Result<usize> {
+        use super::schema::{
+            categories, favourites, history, invites, manga, manga_tags, password_resets,
+            refresh_tokens, sessions, tags, users,
+        };
+
+        let mut write = |line: &Line| -> Result<()> {
+            serde_json::to_writer(&mut out, line)?;
+            out.write_all(b"\n")?;
+            Ok(())
+        };
+        write(&Line::Header(Header {
+            version: dump::VERSION,
+            server_version: crate::VERSION.to_string(),
+        }))?;
+
+        let mut count = 0;
+        macro_rules! dump {
+            ($conn:ident, $table:ident, $model:ident, $line:ident) => {
+                for row in $table::table
+                    .select($model::as_select())
+                    .load_iter::<$model, DefaultLoadingMode>($conn)?
+                {
+                    write(&Line::$line(row?))?;
+                    count += 1;
+                }
+            };
+        }
+
+        // reading in one transaction to get consistent snapshot
+        self.pool()?.transaction::<_, anyhow::Error, _>(|conn| {
+            dump!(conn, users, User, Users);
+            dump!(conn, sessions, Session, Sessions);
+            dump!(conn, refresh_tokens, RefreshToken, RefreshTokens);
+            dump!(conn, invites, Invite, Invites);
+            dump!(conn, password_resets, PasswordReset, PasswordResets);
+            dump!(conn, manga, Manga, Manga);
+            dump!(conn, tags, Tag, Tags);
+            dump!(conn, manga_tags, MangaTags, MangaTags);
+            dump!(conn, categories, Category, Categories);
+            dump!(conn, favourites, Favourite, Favourites);
+            dump!(conn, history, History, History);
+            Ok(())
+        })?;
+        Ok(count)
+    }
+    /// Load dump, written by any backend, into empty database. Returns number
+    /// of rows
+    pub fn load(&self, input: impl BufRead) -> Result<usize> {
+        use super::schema::{
+            categories, favourites, history, invites, manga, manga_tags, password_resets,
+            refresh_tokens, sessions, tags, users,
+        };
+
+        self.pool()?.transaction::<_, anyhow::Error, _>(|conn| {
+            let users_count: i64 = users::table.count().get_result(conn)?;
+            let manga_count: i64 = manga::table.count().get_result(conn)?;
+            if users_count > 0 || manga_count > 0 {
+                return Err(anyhow!("database is not empty"));
+            }
+
+            let mut lines = input.lines();
+            let header = lines.next().context("dump is empty")??;
+            match serde_json::from_str(&header).context("failed to read header")? {
+                Line::Header(h) if h.version == dump::VERSION => (),
+                Line::Header(h) => return Err(anyhow!("unsupported dump version {}", h.version)),
+                _ => return Err(anyhow!("dump has no header")),
+            }
+
+            let mut count = 0;
+            for (i, line) in lines.enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // header is the first line
+                let line_number = i + 2;
+                let line: Line = serde_json::from_str(&line)
+                    .with_context(|| format!("failed to read line {line_number}"))?;
+
+                macro_rules! insert {
+                    ($table:ident, $row:expr) => {
+                        diesel::insert_into($table::table)
+                            .values($row)
+                            .execute(&mut *conn)
+                            .with_context(|| format!("failed to insert line {line_number}"))?
+                    };
+                }
+                match line {
+                    Line::Header(_) => {
+                        return Err(anyhow!("unexpected header on line {line_number}"));
+                    }
+                    Line::Users(row) => insert!(users, &row),
+                    Line::Sessions(row) => insert!(sessions, &row),
+                    Line::RefreshTokens(row) => insert!(refresh_tokens, &row),
+                    Line::Invites(row) => insert!(invites, &row),
+                    Line::PasswordResets(row) => insert!(password_resets, &row),
+                    Line::Manga(row) => {
+                        insert!(manga, &row);
+                        #[cfg(feature = "sqlite")]
+                        Self::index_manga(conn, &row)?;
+                        1
+                    }
+                    Line::Tags(row) => insert!(tags, &row),
+                    Line::MangaTags(row) => insert!(manga_tags, &row),
+                    Line::Categories(row) => insert!(categories, &row),
+                    Line::Favourites(row) => insert!(favourites, &row),
+                    Line::History(row) => insert!(history, &row),
+                };
+                count += 1;
+            }
+            Ok(count)
+        })
+    }
+}
+
+/// Wait for lock of database, held by other connection, instead of failing
+/// with "database is locked"
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+struct SqliteBusyTimeout;
+
+#[cfg(feature = "sqlite")]
+impl diesel::r2d2::CustomizeConnection<DbConnection, diesel::r2d2::Error> for SqliteBusyTimeout {
+    fn on_acquire(&self, conn: &mut DbConnection) -> Result<(), diesel::r2d2::Error> {
+        use diesel::connection::SimpleConnection;
+
+        conn.batch_execute("PRAGMA busy_timeout = 5000;")
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+/// Time to mark saved rows as modified. It's not earlier than the last sync
+/// timestamp, so rows are received by clients, which got it, even if clock
+/// went back
+fn modification_time(synchronized: Option<Time>) -> Time {
+    current_timestamp()
+        .unwrap_or_default()
+        .max(synchronized.unwrap_or_default())
 }
 
-fn migrate(conn: &mut impl MigrationHarness<Backend>) -> Result<()> {
-    conn.run_pending_migrations(MIGRATIONS)
-        .map_err(|e| anyhow!("failed to run migrations: {e}"))?;
-    Ok(())
+/// Data was synchronized after client's last known `timestamp`
+fn changed_since(synchronized: Option<Time>, timestamp: Option<Time>) -> bool {
+    synchronized.is_some_and(|s| timestamp.is_none_or(|t| t < s))
 }
@@ -0,0 +1,36 @@
+//! Backend-neutral dump of all tables as JSON lines. The first line is header,
+//! others are rows, ordered so tables are loaded after ones they reference
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::db::{
+    Category, Favourite, History, Invite, Manga, MangaTags, PasswordReset, RefreshToken, Session,
+    Tag, User,
+};
+
+/// Version of dump format, increased on incompatible changes
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "table", content = "row", rename_all = "snake_case")]
+pub enum Line {
+    Header(Header),
+    Users(User),
+    Sessions(Session),
+    RefreshTokens(RefreshToken),
+    Invites(Invite),
+    PasswordResets(PasswordReset),
+    Manga(Manga),
+    Tags(Tag),
+    MangaTags(MangaTags),
+    Categories(Category),
+    Favourites(Favourite),
+    History(History),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Header {
+    pub version: u32,
+    /// Version of server, which made dump
+    pub server_version: String,
+}
@@ -1,4 +1,5 @@
 pub mod conn;
+pub mod dump;
 
 #[rustfmt::skip]
 pub mod schema;
@@ -11,6 +11,7 @@ diesel::table! {
         track -> Bool,
         show_in_lib -> Bool,
         deleted_at -> BigInt,
+        modified_at -> BigInt,
     }
 }
 
@@ -23,6 +24,7 @@ diesel::table! {
         deleted_at -> BigInt,
         user_id -> Integer,
         pinned -> Bool,
+        modified_at -> BigInt,
     }
 }
 
@@ -38,6 +40,18 @@ diesel::table! {
         chapters -> Integer,
         deleted_at -> BigInt,
         user_id -> Integer,
+        modified_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    invites (id) {
+        id -> Integer,
+        code -> Text,
+        max_uses -> Integer,
+        uses -> Integer,
+        created_at -> BigInt,
+        expires_at -> Nullable<BigInt>,
     }
 }
 
@@ -65,6 +79,39 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    password_resets (id) {
+        id -> Integer,
+        user_id -> Integer,
+        token_hash -> Text,
+        created_at -> BigInt,
+        expires_at -> BigInt,
+        used_at -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Integer,
+        session_id -> Integer,
+        token_hash -> Text,
+        expires_at -> BigInt,
+        used_at -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Integer,
+        user_id -> Integer,
+        created_at -> BigInt,
+        revoked_at -> Nullable<BigInt>,
+        device_name -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        last_seen_at -> BigInt,
+    }
+}
+
 diesel::table! {
     tags (id) {
         id -> BigInt,
@@ -82,6 +129,7 @@ diesel::table! {
         nickname -> Nullable<Text>,
         favourites_sync_timestamp -> Nullable<BigInt>,
         history_sync_timestamp -> Nullable<BigInt>,
+        disabled_at -> Nullable<BigInt>,
     }
 }
 
@@ -92,13 +140,20 @@ diesel::joinable!(history -> manga (manga_id));
 diesel::joinable!(history -> users (user_id));
 diesel::joinable!(manga_tags -> manga (manga_id));
 diesel::joinable!(manga_tags -> tags (tag_id));
+diesel::joinable!(password_resets -> users (user_id));
+diesel::joinable!(refresh_tokens -> sessions (session_id));
+diesel::joinable!(sessions -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     categories,
     favourites,
     history,
+    invites,
     manga,
     manga_tags,
+    password_resets,
+    refresh_tokens,
+    sessions,
     tags,
     users,
 );
@@ -0,0 +1,12 @@
+use anyhow::Result;
+
+use crate::{config::ConfGC, current_timestamp, db::conn::DB, models::admin::GCStats};
+
+/// Remove soft-deleted rows older than retention and manga, which are not
+/// referenced anymore
+pub fn run(db: &DB, conf: &ConfGC) -> Result<GCStats> {
+    let now = current_timestamp().unwrap_or_default();
+    let stats = db.gc(now - conf.retention as i64 * 1000)?;
+    log::info!("gc finished: {stats:?}");
+    Ok(stats)
+}
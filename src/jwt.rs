@@ -4,34 +4,39 @@ use jsonwebtoken::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{config::ConfJWT, models::common::UserID};
+use crate::{
+    config::ConfJWT,
+    models::common::{SessionID, Time, UserID},
+};
 
-/// 30 days
-const LIFETIME_SEC: u64 = 30 * 24 * 60 * 60;
 const JWT_AUD_PREFIX: &str = "/resource";
+const REFRESH_TOKEN_LEN: usize = 48;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub aud: String,
     pub iss: String,
     pub user_id: UserID,
+    /// Session, to which token belongs
+    pub sid: SessionID,
     pub exp: u64,
 }
 
-pub fn generate(user_id: UserID, config: &ConfJWT) -> Result<String> {
+pub fn generate(user_id: UserID, session_id: SessionID, config: &ConfJWT) -> Result<String> {
     Ok(encode(
         &Header::default(),
         &Claims {
             aud: config.issuer.clone() + JWT_AUD_PREFIX,
             iss: config.issuer.clone(),
             user_id,
-            exp: get_current_timestamp() + LIFETIME_SEC,
+            sid: session_id,
+            exp: get_current_timestamp() + config.access_lifetime,
         },
         &EncodingKey::from_secret(config.secret.as_bytes()),
     )?)
 }
 
-pub fn validate(token: &str) -> Result<UserID> {
+pub fn validate(token: &str) -> Result<Claims> {
     let config = crate::get_config()?;
     let mut validation = Validation::default();
     validation.set_audience(&[config.jwt.issuer.clone() + JWT_AUD_PREFIX]);
@@ -43,5 +48,20 @@ pub fn validate(token: &str) -> Result<UserID> {
         &validation,
     )?;
 
-    Ok(token.claims.user_id)
+    Ok(token.claims)
+}
+
+/// Generate random opaque refresh token
+pub fn generate_refresh() -> String {
+    crate::random_string(REFRESH_TOKEN_LEN)
+}
+
+/// Hash of opaque token, only hashes are stored in database
+pub fn hash_token(token: &str) -> String {
+    blake3::hash(token.as_bytes()).to_string()
+}
+
+/// Expiration time of refresh token, issued now, in milliseconds
+pub fn refresh_expires_at(config: &ConfJWT) -> Time {
+    (get_current_timestamp() + config.refresh_lifetime) as Time * 1000
 }
@@ -7,21 +7,32 @@ use std::{
 };
 
 use anyhow::{Context, Result, anyhow};
+use clap::Parser;
 use confique::Config;
 use log::LevelFilter;
+use rand::{Rng, distributions::Alphanumeric};
 use rocket::{Build, Rocket, data::Limits, routes};
 use simplelog::{ColorChoice, ConfigBuilder, TermLogger, TerminalMode};
 
+use cli::{Cli, Command};
 use config::Conf;
 use db::conn::DB;
 use models::common::Time;
+use ratelimit::RateLimiter;
+use scheduler::Scheduler;
 
+mod backup;
+mod cli;
 mod config;
 mod db;
+mod gc;
 mod jwt;
 mod models;
+mod ratelimit;
 mod request;
 mod routes;
+mod scheduler;
+mod snapshot;
 
 #[cfg(test)]
 mod tests;
@@ -38,6 +49,7 @@ fn get_config() -> Result<&'static Conf> {
 
 #[rocket::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
     eprintln!("Kotync v{VERSION}");
 
     init_logger()?;
@@ -46,8 +58,17 @@ async fn main() -> Result<()> {
     let config = Conf::builder().env().file("config.toml").load()?;
     log::info!("loaded config\n{config}");
 
-    let db = DB::new(config.db.clone())?;
-    rocket(config, db)?.launch().await?;
+    match cli.command.unwrap_or_default() {
+        Command::Serve => {
+            let db = DB::new(config.db.clone())?;
+            rocket(config, db)?.launch().await?;
+        }
+        Command::Migrate { revert } => cli::migrate(config.db, revert)?,
+        Command::User(command) => cli::user(&DB::new(config.db)?, command)?,
+        Command::CheckConfig => println!("{config}"),
+        Command::Export { path } => cli::export(&DB::new(config.db)?, &path)?,
+        Command::Import { path } => cli::import(&DB::new(config.db)?, &path)?,
+    }
 
     Ok(())
 }
@@ -62,19 +83,39 @@ fn rocket(config: Conf, db: DB) -> Result<Rocket<Build>> {
         .configure(rocket::Config {
             port: config.server.port,
             address: IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
-            limits: Limits::default().limit("json", config.server.limits.json),
+            ip_header: config.server.ip_header.clone().map(Into::into),
+            limits: Limits::default()
+                .limit("json", config.server.limits.json)
+                .limit("bytes", config.server.limits.backup),
             ..Default::default()
         })
         .manage(config.clone())
         .manage(db)
+        .manage(RateLimiter::new(config.server.rate_limit.clone()))
+        .manage(Scheduler::new(&config))
+        .attach(Scheduler::fairing())
+        .attach(Scheduler::shutdown_fairing())
         .mount(
             "/",
             routes![
                 routes::base::root,
                 routes::base::auth,
+                routes::base::refresh,
+                routes::base::logout,
+                routes::base::reset_password,
+                routes::base::register,
                 routes::base::me,
+                routes::base::export,
+                routes::base::change_password,
+                routes::base::delete_me,
+                routes::base::list_sessions,
+                routes::base::delete_session,
                 routes::base::get_manga,
                 routes::base::list_manga,
+                routes::base::search_manga,
+                routes::base::list_tags,
+                routes::base::get_tag,
+                routes::base::list_tag_manga,
             ],
         )
         .mount(
@@ -84,6 +125,7 @@ fn rocket(config: Conf, db: DB) -> Result<Rocket<Build>> {
                 routes::resource::get_favourites,
                 routes::resource::save_history,
                 routes::resource::get_history,
+                routes::resource::import_backup,
             ],
         )
         .mount("/", routes![routes::base::fallback]);
@@ -93,7 +135,34 @@ fn rocket(config: Conf, db: DB) -> Result<Rocket<Build>> {
             log::error!("ADMIN_API should start with /");
             return Err(anyhow!("invalid env, exiting"));
         }
-        rocket = rocket.mount(admin, routes![routes::admin::stats, routes::admin::info]);
+        if config
+            .server
+            .admin_token
+            .as_ref()
+            .is_none_or(|t| t.is_empty())
+        {
+            log::error!("ADMIN_TOKEN is required when ADMIN_API is set");
+            return Err(anyhow!("invalid env, exiting"));
+        }
+        rocket = rocket.mount(
+            admin,
+            routes![
+                routes::admin::stats,
+                routes::admin::info,
+                routes::admin::create_invite,
+                routes::admin::list_invites,
+                routes::admin::create_password_reset,
+                routes::admin::list_users,
+                routes::admin::get_user,
+                routes::admin::disable_user,
+                routes::admin::enable_user,
+                routes::admin::delete_user,
+                routes::admin::run_gc,
+                routes::admin::run_backup,
+                routes::admin::list_jobs,
+                routes::admin::run_job,
+            ],
+        );
     }
 
     Ok(rocket)
@@ -158,6 +227,15 @@ fn logger_level() -> LevelFilter {
     DEFAULT
 }
 
+/// Random alphanumeric string, suitable for tokens
+pub fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
 /// Current system time in milliseconds
 pub fn current_timestamp() -> Option<Time> {
     SystemTime::now()
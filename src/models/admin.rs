@@ -1,4 +1,6 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use super::common::{Time, UserID};
 
 #[derive(Debug, Serialize)]
 #[cfg_attr(test, derive(serde::Deserialize))]
@@ -7,7 +9,112 @@ pub struct DBStats {
     pub manga_count: u64,
 }
 
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct RateLimitStats {
+    pub failed_attempts: u64,
+    pub blocked_requests: u64,
+    pub locked_keys: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct Stats {
+    #[serde(flatten)]
+    pub db: DBStats,
+    pub rate_limit: RateLimitStats,
+    /// Time of the newest database backup
+    pub last_backup_at: Option<Time>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct Backup {
+    pub path: String,
+    pub created_at: Time,
+    /// Size of file in bytes
+    pub size: u64,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ServerInfo {
     pub server_version: String,
 }
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct Invite {
+    pub code: String,
+    pub max_uses: i32,
+    pub uses: i32,
+    pub created_at: Time,
+    pub expires_at: Option<Time>,
+}
+
+#[derive(Debug, Deserialize)]
+#[cfg_attr(test, derive(serde::Serialize))]
+pub struct CreateInvite {
+    /// How many users can register with invite
+    #[serde(default = "one")]
+    pub max_uses: i32,
+    /// Lifetime of invite in seconds. If not set, invite doesn't expire
+    pub expires_in: Option<u64>,
+}
+
+const fn one() -> i32 {
+    1
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct PasswordReset {
+    pub user_id: UserID,
+    /// Single-use token for `post /auth/reset`
+    pub token: String,
+    pub expires_at: Time,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct User {
+    pub id: UserID,
+    pub email: String,
+    pub nickname: Option<String>,
+    pub favourites_sync_timestamp: Option<Time>,
+    pub history_sync_timestamp: Option<Time>,
+    /// Not deleted favourites
+    pub favourites_count: u64,
+    /// Not deleted history entries
+    pub history_count: u64,
+    /// Not deleted categories
+    pub categories_count: u64,
+    /// Set when user is disabled
+    pub disabled_at: Option<Time>,
+}
+
+/// Numbers of removed rows
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct GCStats {
+    pub favourites: u64,
+    pub categories: u64,
+    pub history: u64,
+    pub manga: u64,
+    pub tags: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct JobStatus {
+    pub name: String,
+    /// Interval between runs in seconds. If not set, job is started only
+    /// manually
+    pub interval: Option<u64>,
+    pub running: bool,
+    pub runs: u64,
+    pub failures: u64,
+    pub last_started_at: Option<Time>,
+    pub last_finished_at: Option<Time>,
+    /// Error of last run, if it failed
+    pub last_error: Option<String>,
+}
@@ -0,0 +1,240 @@
+//! Entries of Kotatsu app backup. Fields missing in older app versions have
+//! defaults
+
+use serde::{Deserialize, Serialize};
+
+use super::common::{self, ContentRating, MangaState, Time};
+
+/// Metadata of backup
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Index {
+    pub app_id: String,
+    pub app_version: i32,
+    pub created_at: Time,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Category {
+    pub category_id: i64,
+    pub created_at: Time,
+    pub sort_key: i32,
+    pub title: String,
+    #[serde(default)]
+    pub order: Option<String>,
+    #[serde(default)]
+    pub track: Option<bool>,
+    #[serde(default)]
+    pub show_in_lib: Option<bool>,
+}
+
+impl Category {
+    pub fn from_api(category: &common::Category) -> Self {
+        Self {
+            category_id: category.id,
+            created_at: category.created_at,
+            sort_key: category.sort_key,
+            title: category.title.clone(),
+            order: Some(category.order.clone()),
+            track: Some(category.track),
+            show_in_lib: Some(category.show_in_lib),
+        }
+    }
+    pub fn to_api(&self) -> common::Category {
+        common::Category {
+            id: self.category_id,
+            created_at: self.created_at,
+            sort_key: self.sort_key,
+            track: self.track.unwrap_or(true),
+            title: self.title.clone(),
+            order: self.order.clone().unwrap_or_else(|| "NEWEST".to_string()),
+            deleted_at: 0,
+            show_in_lib: self.show_in_lib.unwrap_or(true),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Favourite {
+    pub manga_id: i64,
+    pub category_id: i64,
+    #[serde(default)]
+    pub sort_key: i32,
+    #[serde(default)]
+    pub pinned: bool,
+    pub created_at: Time,
+    pub manga: Manga,
+}
+
+impl Favourite {
+    pub fn from_api(favourite: &common::Favourite) -> Self {
+        Self {
+            manga_id: favourite.manga_id,
+            category_id: favourite.category_id,
+            sort_key: favourite.sort_key,
+            pinned: favourite.pinned,
+            created_at: favourite.created_at,
+            manga: Manga::from_api(&favourite.manga),
+        }
+    }
+    pub fn to_api(&self) -> common::Favourite {
+        common::Favourite {
+            manga_id: self.manga_id,
+            manga: self.manga.to_api(),
+            category_id: self.category_id,
+            sort_key: self.sort_key,
+            pinned: self.pinned,
+            created_at: self.created_at,
+            deleted_at: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct History {
+    pub manga_id: i64,
+    pub created_at: Time,
+    pub updated_at: Time,
+    pub chapter_id: i64,
+    pub page: i32,
+    pub scroll: f64,
+    #[serde(default = "common::minus_1")]
+    pub percent: f64,
+    #[serde(default = "common::minus_1")]
+    pub chapters: i32,
+    pub manga: Manga,
+}
+
+impl History {
+    pub fn from_api(history: &common::History) -> Self {
+        Self {
+            manga_id: history.manga_id,
+            created_at: history.created_at,
+            updated_at: history.updated_at,
+            chapter_id: history.chapter_id,
+            page: history.page,
+            scroll: history.scroll,
+            percent: history.percent,
+            chapters: history.chapters,
+            manga: Manga::from_api(&history.manga),
+        }
+    }
+    pub fn to_api(&self) -> common::History {
+        common::History {
+            manga_id: self.manga_id,
+            manga: self.manga.to_api(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            chapter_id: self.chapter_id,
+            page: self.page,
+            scroll: self.scroll,
+            percent: self.percent,
+            chapters: self.chapters,
+            deleted_at: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Manga {
+    pub id: i64,
+    pub title: String,
+    #[serde(default)]
+    pub alt_title: Option<String>,
+    pub url: String,
+    #[serde(default)]
+    pub public_url: String,
+    #[serde(default)]
+    pub rating: f32,
+    /// Used before `content_rating`
+    #[serde(default)]
+    pub nsfw: bool,
+    #[serde(default)]
+    pub content_rating: Option<String>,
+    #[serde(default)]
+    pub cover_url: String,
+    #[serde(default)]
+    pub large_cover_url: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    pub source: String,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+}
+
+impl Manga {
+    pub fn from_api(manga: &common::Manga) -> Self {
+        Self {
+            id: manga.id,
+            title: manga.title.clone(),
+            alt_title: manga.alt_title.clone(),
+            url: manga.url.clone(),
+            public_url: manga.public_url.clone(),
+            rating: manga.rating,
+            nsfw: manga.content_rating == Some(ContentRating::Adult),
+            content_rating: manga.content_rating.map(|r| r.to_string()),
+            cover_url: manga.cover_url.clone(),
+            large_cover_url: manga.large_cover_url.clone(),
+            state: manga.state.map(|s| s.to_string()),
+            author: manga.author.clone(),
+            source: manga.source.clone(),
+            tags: manga
+                .tags
+                .iter()
+                .map(|t| Tag {
+                    id: t.id,
+                    title: t.title.clone(),
+                    key: t.key.clone(),
+                    source: t.source.clone(),
+                    pinned: false,
+                })
+                .collect(),
+        }
+    }
+    pub fn to_api(&self) -> common::Manga {
+        let content_rating = match &self.content_rating {
+            Some(r) => r.parse().ok(),
+            None => self.nsfw.then_some(ContentRating::Adult),
+        };
+        common::Manga {
+            id: self.id,
+            title: self.title.clone(),
+            alt_title: self.alt_title.clone(),
+            url: self.url.clone(),
+            public_url: self.public_url.clone(),
+            rating: self.rating,
+            content_rating,
+            cover_url: self.cover_url.clone(),
+            large_cover_url: self.large_cover_url.clone(),
+            tags: self.tags.iter().map(|t| t.to_api()).collect(),
+            state: self
+                .state
+                .as_deref()
+                .and_then(|s| s.parse::<MangaState>().ok()),
+            author: self.author.clone(),
+            source: self.source.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Tag {
+    pub id: i64,
+    pub title: String,
+    pub key: String,
+    pub source: String,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl Tag {
+    pub fn to_api(&self) -> common::MangaTag {
+        common::MangaTag {
+            id: self.id,
+            title: self.title.clone(),
+            key: self.key.clone(),
+            source: self.source.clone(),
+        }
+    }
+}
@@ -11,6 +11,7 @@ use super::{
 
 pub type Time = i64;
 pub type UserID = i32;
+pub type SessionID = i32;
 
 #[derive(
     Debug, Clone, Copy, PartialEq, Deserialize, Serialize, Default, EnumString, strum::Display,
@@ -68,7 +69,7 @@ pub struct Category {
 }
 
 impl Category {
-    pub fn to_db(&self, user_id: i32) -> DBCategory {
+    pub fn to_db(&self, user_id: i32, modified_at: Time) -> DBCategory {
         DBCategory {
             id: self.id,
             created_at: self.created_at,
@@ -79,6 +80,7 @@ impl Category {
             track: self.track,
             show_in_lib: self.show_in_lib,
             deleted_at: self.deleted_at,
+            modified_at,
         }
     }
 }
@@ -96,7 +98,7 @@ pub struct Favourite {
 }
 
 impl Favourite {
-    pub fn to_db(&self, user_id: UserID) -> DBFavourite {
+    pub fn to_db(&self, user_id: UserID, modified_at: Time) -> DBFavourite {
         DBFavourite {
             manga_id: self.manga_id,
             category_id: self.category_id,
@@ -105,6 +107,7 @@ impl Favourite {
             created_at: self.created_at,
             deleted_at: self.deleted_at,
             user_id,
+            modified_at,
         }
     }
 }
@@ -124,12 +127,12 @@ pub struct History {
     pub deleted_at: Time,
 }
 
-const fn minus_1() -> i32 {
-    -1
+pub fn minus_1<T: From<i8>>() -> T {
+    T::from(-1)
 }
 
 impl History {
-    pub fn to_db(&self, user_id: UserID) -> DBHistory {
+    pub fn to_db(&self, user_id: UserID, modified_at: Time) -> DBHistory {
         DBHistory {
             manga_id: self.manga.id,
             created_at: self.created_at,
@@ -141,6 +144,7 @@ impl History {
             chapters: self.chapters,
             deleted_at: self.deleted_at,
             user_id,
+            modified_at,
         }
     }
 }
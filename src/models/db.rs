@@ -6,13 +6,20 @@ use diesel::sqlite::Sqlite as Backend;
 #[cfg(feature = "mysql")]
 use diesel::mysql::Mysql as Backend;
 use log::error;
+use serde::{Deserialize, Serialize};
 
+use super::TruncatedString;
+use super::admin::Invite as ApiInvite;
 use super::common::{
     Category as ApiCategory, Favourite as ApiFavourite, History as ApiHistory, Manga as ApiManga,
-    MangaTag as ApiMangaTag, Time, UserID,
+    MangaTag as ApiMangaTag, SessionID, Time, UserID,
 };
+use super::response::Session as ApiSession;
+use crate::current_timestamp;
 
-#[derive(Queryable, Selectable, Insertable, Identifiable, AsChangeset, Debug)]
+#[derive(
+    Queryable, Selectable, Insertable, Identifiable, AsChangeset, Serialize, Deserialize, Debug,
+)]
 #[diesel(
     table_name = crate::db::schema::categories,
     check_for_backend(Backend),
@@ -28,6 +35,8 @@ pub struct Category {
     pub track: bool,
     pub show_in_lib: bool,
     pub deleted_at: Time,
+    /// Server-side time of last change
+    pub modified_at: Time,
 }
 
 impl Category {
@@ -45,7 +54,7 @@ impl Category {
     }
 }
 
-#[derive(Queryable, Selectable, Insertable, Debug)]
+#[derive(Queryable, Selectable, Insertable, Serialize, Deserialize, Debug)]
 #[diesel(
     table_name = crate::db::schema::favourites,
     check_for_backend(Backend)
@@ -58,6 +67,8 @@ pub struct Favourite {
     pub created_at: Time,
     pub deleted_at: Time,
     pub user_id: UserID,
+    /// Server-side time of last change
+    pub modified_at: Time,
 }
 
 impl Favourite {
@@ -74,7 +85,7 @@ impl Favourite {
     }
 }
 
-#[derive(Queryable, Selectable, Insertable, AsChangeset, Debug)]
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Serialize, Deserialize, Debug)]
 #[diesel(
     table_name = crate::db::schema::history,
     check_for_backend(Backend)
@@ -90,6 +101,8 @@ pub struct History {
     pub chapters: i32,
     pub deleted_at: Time,
     pub user_id: UserID,
+    /// Server-side time of last change
+    pub modified_at: Time,
 }
 
 impl History {
@@ -109,7 +122,17 @@ impl History {
     }
 }
 
-#[derive(Queryable, Selectable, Insertable, Identifiable, AsChangeset, Debug)]
+#[derive(
+    Queryable,
+    QueryableByName,
+    Selectable,
+    Insertable,
+    Identifiable,
+    AsChangeset,
+    Serialize,
+    Deserialize,
+    Debug,
+)]
 #[diesel(
     table_name = crate::db::schema::manga,
     check_for_backend(Backend)
@@ -163,7 +186,9 @@ impl Manga {
     }
 }
 
-#[derive(Identifiable, Selectable, Insertable, Queryable, Associations, Debug)]
+#[derive(
+    Identifiable, Selectable, Insertable, Queryable, Associations, Serialize, Deserialize, Debug,
+)]
 #[diesel(belongs_to(Manga))]
 #[diesel(belongs_to(Tag))]
 #[diesel(table_name = crate::db::schema::manga_tags)]
@@ -173,7 +198,9 @@ pub struct MangaTags {
     pub tag_id: i64,
 }
 
-#[derive(Queryable, Selectable, Insertable, Identifiable, AsChangeset, Debug)]
+#[derive(
+    Queryable, Selectable, Insertable, Identifiable, AsChangeset, Serialize, Deserialize, Debug,
+)]
 #[diesel(
     table_name = crate::db::schema::tags,
     check_for_backend(Backend)
@@ -202,7 +229,9 @@ impl Tag {
     }
 }
 
-#[derive(Queryable, Selectable, Identifiable, Debug, Default)]
+#[derive(
+    Queryable, Selectable, Insertable, Identifiable, Serialize, Deserialize, Debug, Default,
+)]
 #[diesel(
     table_name = crate::db::schema::users,
     check_for_backend(Backend)
@@ -214,6 +243,8 @@ pub struct User {
     pub nickname: Option<String>,
     pub favourites_sync_timestamp: Option<Time>,
     pub history_sync_timestamp: Option<Time>,
+    /// When user was disabled by admin
+    pub disabled_at: Option<Time>,
 }
 
 #[derive(Insertable, Debug)]
@@ -225,3 +256,145 @@ pub struct UserInsert {
     pub email: String,
     pub password_hash: String,
 }
+
+#[derive(Queryable, Selectable, Insertable, Identifiable, Serialize, Deserialize, Debug)]
+#[diesel(
+    table_name = crate::db::schema::sessions,
+    check_for_backend(Backend)
+)]
+pub struct Session {
+    pub id: SessionID,
+    pub user_id: UserID,
+    pub created_at: Time,
+    pub revoked_at: Option<Time>,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub last_seen_at: Time,
+}
+
+impl Session {
+    pub fn to_api(&self, current: SessionID) -> ApiSession {
+        ApiSession {
+            id: self.id,
+            device_name: self.device_name.clone(),
+            user_agent: self.user_agent.clone(),
+            created_at: self.created_at,
+            last_seen_at: self.last_seen_at,
+            current: self.id == current,
+        }
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(
+    table_name = crate::db::schema::sessions,
+    check_for_backend(Backend)
+)]
+pub struct SessionInsert {
+    pub user_id: UserID,
+    pub created_at: Time,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub last_seen_at: Time,
+}
+
+impl SessionInsert {
+    pub fn new(user_id: UserID, device_name: Option<String>, user_agent: Option<String>) -> Self {
+        let now = current_timestamp().unwrap_or_default();
+        Self {
+            user_id,
+            created_at: now,
+            device_name: device_name.map(|d| d.truncated(100)),
+            user_agent: user_agent.map(|u| u.truncated(255)),
+            last_seen_at: now,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Insertable, Identifiable, Serialize, Deserialize, Debug)]
+#[diesel(
+    table_name = crate::db::schema::refresh_tokens,
+    check_for_backend(Backend)
+)]
+pub struct RefreshToken {
+    pub id: i32,
+    pub session_id: SessionID,
+    pub token_hash: String,
+    pub expires_at: Time,
+    pub used_at: Option<Time>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(
+    table_name = crate::db::schema::refresh_tokens,
+    check_for_backend(Backend)
+)]
+pub struct RefreshTokenInsert {
+    pub session_id: SessionID,
+    pub token_hash: String,
+    pub expires_at: Time,
+}
+
+#[derive(Queryable, Selectable, Insertable, Identifiable, Serialize, Deserialize, Debug)]
+#[diesel(
+    table_name = crate::db::schema::invites,
+    check_for_backend(Backend)
+)]
+pub struct Invite {
+    pub id: i32,
+    pub code: String,
+    pub max_uses: i32,
+    pub uses: i32,
+    pub created_at: Time,
+    pub expires_at: Option<Time>,
+}
+
+impl Invite {
+    pub fn to_api(&self) -> ApiInvite {
+        ApiInvite {
+            code: self.code.clone(),
+            max_uses: self.max_uses,
+            uses: self.uses,
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(
+    table_name = crate::db::schema::invites,
+    check_for_backend(Backend)
+)]
+pub struct InviteInsert {
+    pub code: String,
+    pub max_uses: i32,
+    pub created_at: Time,
+    pub expires_at: Option<Time>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(
+    table_name = crate::db::schema::password_resets,
+    check_for_backend(Backend)
+)]
+pub struct PasswordResetInsert {
+    pub user_id: UserID,
+    pub token_hash: String,
+    pub created_at: Time,
+    pub expires_at: Time,
+}
+
+#[derive(Queryable, Selectable, Insertable, Identifiable, Serialize, Deserialize, Debug)]
+#[diesel(
+    table_name = crate::db::schema::password_resets,
+    check_for_backend(Backend)
+)]
+pub struct PasswordReset {
+    pub id: i32,
+    pub user_id: UserID,
+    pub token_hash: String,
+    pub created_at: Time,
+    pub expires_at: Time,
+    pub used_at: Option<Time>,
+}
@@ -1,6 +1,9 @@
 use unicode_segmentation::UnicodeSegmentation;
 
+use common::Time;
+
 pub mod admin;
+pub mod backup;
 pub mod common;
 pub mod db;
 pub mod request;
@@ -15,3 +18,26 @@ impl TruncatedString for String {
         self.graphemes(true).take(len).collect()
     }
 }
+
+/// Synced item, which is merged by last-writer-wins: newer version is kept
+pub trait Versioned {
+    /// Time of last client-side change
+    fn version(&self) -> Time;
+}
+
+/// Version is the latest of timestamps, which are changed by client
+macro_rules! versioned {
+    ($fields:tt => $($ty:ty),+) => {
+        $(versioned!(@impl $ty, $fields);)+
+    };
+    (@impl $ty:ty, [$($field:ident),+]) => {
+        impl Versioned for $ty {
+            fn version(&self) -> Time {
+                Time::MIN$(.max(self.$field))+
+            }
+        }
+    };
+}
+
+versioned!([created_at, deleted_at] => common::Category, db::Category, common::Favourite, db::Favourite);
+versioned!([created_at, updated_at, deleted_at] => common::History, db::History);
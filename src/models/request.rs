@@ -1,4 +1,11 @@
-use anyhow::Result;
+use std::sync::OnceLock;
+
+use anyhow::{Result, anyhow};
+use argon2::{
+    Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use rocket::{FromForm, UriDisplayQuery};
 use serde::Deserialize;
 use zeroize::ZeroizeOnDrop;
 
@@ -6,6 +13,8 @@ use super::db::User;
 
 #[cfg(feature = "migrate-md5")]
 pub const MD5_LEN: usize = 32;
+/// Length of blake3 hash in hex form, used before argon2
+const BLAKE3_LEN: usize = 64;
 
 #[derive(Debug, Clone, Deserialize, ZeroizeOnDrop)]
 #[cfg_attr(test, derive(serde::Serialize))]
@@ -15,10 +24,66 @@ pub struct Auth {
 
     pub password: String,
 
-    #[cfg(feature = "migrate-md5")]
-    #[serde(skip_deserializing)]
+    /// Optional name of device, to distinguish sessions
+    #[serde(default)]
     #[zeroize(skip)]
-    pub password_md5: String,
+    pub device_name: Option<String>,
+
+    /// Invite code, allows to register when registration is disabled
+    #[serde(default)]
+    pub invite: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ZeroizeOnDrop)]
+#[cfg_attr(test, derive(serde::Serialize))]
+pub struct Refresh {
+    pub refresh_token: String,
+}
+
+#[cfg(test)]
+impl Refresh {
+    pub fn new(refresh_token: &str) -> Self {
+        Self {
+            refresh_token: refresh_token.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ZeroizeOnDrop)]
+#[cfg_attr(test, derive(serde::Serialize))]
+pub struct ChangePassword {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, ZeroizeOnDrop)]
+#[cfg_attr(test, derive(serde::Serialize))]
+pub struct ResetPassword {
+    pub token: String,
+    pub new_password: String,
+}
+
+/// Filters of manga list, combined with AND
+#[derive(Debug, Default, FromForm, UriDisplayQuery)]
+pub struct MangaFilter {
+    /// Substring of title or alt title
+    pub q: Option<String>,
+    pub source: Option<String>,
+    /// One of `MangaState`
+    pub state: Option<String>,
+    /// One of `ContentRating`
+    pub content_rating: Option<String>,
+    pub author: Option<String>,
+    /// Key of tag
+    pub tag: Option<String>,
+}
+
+/// Result of successful password check
+#[derive(Debug, PartialEq)]
+pub enum PasswordCheck {
+    Valid,
+    /// Password is correct, but stored hash should be replaced with a new one
+    Outdated,
 }
 
 impl Auth {
@@ -27,40 +92,119 @@ impl Auth {
         Self {
             email: email.to_owned(),
             password: password.to_owned(),
-            #[cfg(feature = "migrate-md5")]
-            password_md5: "".to_string(),
+            device_name: None,
+            invite: None,
         }
     }
 
-    /// Validate fields and hash password
+    /// Validate fields
     pub fn parse(self) -> Result<Self, &'static str> {
-        if !matches!(self.password.len(), 2..=24) {
-            return Err("Password should be from 2 to 24 characters long");
-        }
-        if !matches!(self.email.len(), 5..=320) || !self.email.contains('@') {
-            return Err("Invalid email address");
-        }
+        validate_password(&self.password)?;
+        validate_email(&self.email)?;
+        Ok(self)
+    }
+
+    pub fn hash_password(&self) -> Result<String> {
+        hash_password(&self.password)
+    }
 
-        Ok(Self {
-            email: self.email.clone(),
-            password: blake3::hash(self.password.as_bytes()).to_string(),
-            #[cfg(feature = "migrate-md5")]
-            password_md5: to_md5(&self.password),
-        })
+    pub fn check_password(&self, user: &User) -> Result<PasswordCheck, ()> {
+        check_password(&self.password, &user.password_hash)
     }
+}
 
-    pub fn check_password(&self, user: &User) -> Result<(), ()> {
-        if self.password == user.password_hash {
-            return Ok(());
+impl ChangePassword {
+    #[cfg(test)]
+    pub fn new(current_password: &str, new_password: &str) -> Self {
+        Self {
+            current_password: current_password.to_owned(),
+            new_password: new_password.to_owned(),
         }
-        // todo: also migrate new passwords for "original"
-        #[cfg(feature = "migrate-md5")]
-        if user.password_hash.len() == MD5_LEN && self.password_md5 == user.password_hash {
-            return Ok(());
+    }
+
+    /// Validate new password
+    pub fn parse(self) -> Result<Self, &'static str> {
+        validate_password(&self.new_password)?;
+        Ok(self)
+    }
+}
+
+impl ResetPassword {
+    #[cfg(test)]
+    pub fn new(token: &str, new_password: &str) -> Self {
+        Self {
+            token: token.to_owned(),
+            new_password: new_password.to_owned(),
         }
+    }
+
+    /// Validate new password
+    pub fn parse(self) -> Result<Self, &'static str> {
+        validate_password(&self.new_password)?;
+        Ok(self)
+    }
+}
+
+pub fn validate_password(password: &str) -> Result<(), &'static str> {
+    if !matches!(password.len(), 2..=24) {
+        return Err("Password should be from 2 to 24 characters long");
+    }
+    Ok(())
+}
 
-        Err(())
+pub fn validate_email(email: &str) -> Result<(), &'static str> {
+    if !matches!(email.len(), 5..=320) || !email.contains('@') {
+        return Err("Invalid email address");
     }
+    Ok(())
+}
+
+/// Hash password with Argon2id. Returns hash in PHC string format
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("failed to hash password: {e}"))?
+        .to_string())
+}
+
+pub fn check_password(password: &str, hash: &str) -> Result<PasswordCheck, ()> {
+    if let Ok(parsed) = PasswordHash::new(hash) {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .map_err(|_| ())?;
+
+        let current = Params::default();
+        let outdated = parsed.algorithm != argon2::Algorithm::default().ident()
+            || Params::try_from(&parsed).is_ok_and(|p| {
+                p.m_cost() != current.m_cost()
+                    || p.t_cost() != current.t_cost()
+                    || p.p_cost() != current.p_cost()
+            });
+        return match outdated {
+            true => Ok(PasswordCheck::Outdated),
+            false => Ok(PasswordCheck::Valid),
+        };
+    }
+
+    if hash.len() == BLAKE3_LEN && blake3::hash(password.as_bytes()).to_hex().as_str() == hash {
+        return Ok(PasswordCheck::Outdated);
+    }
+    #[cfg(feature = "migrate-md5")]
+    if hash.len() == MD5_LEN && to_md5(password) == hash {
+        return Ok(PasswordCheck::Outdated);
+    }
+
+    Err(())
+}
+
+/// Check password against hash of other one, so login with unknown email
+/// takes as long as with wrong password
+pub fn check_password_dummy(password: &str) {
+    static HASH: OnceLock<String> = OnceLock::new();
+
+    let hash = HASH.get_or_init(|| hash_password("dummy").unwrap_or_default());
+    let _ = check_password(password, hash);
 }
 
 #[cfg(feature = "migrate-md5")]
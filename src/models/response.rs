@@ -1,11 +1,12 @@
 use serde::Serialize;
 
-use super::common::UserID;
+use super::common::{Manga, SessionID, Time, UserID};
 
 #[derive(Debug, Serialize)]
 #[cfg_attr(test, derive(serde::Deserialize))]
 pub struct Auth {
     pub token: String,
+    pub refresh_token: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -15,3 +16,43 @@ pub struct Me {
     pub email: String,
     pub nickname: Option<String>,
 }
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct Session {
+    pub id: SessionID,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: Time,
+    pub last_seen_at: Time,
+    /// If this is the session of token used in request
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct MangaPage {
+    pub manga: Vec<Manga>,
+    /// Should be passed as `cursor` to get the next page. `None` on the last
+    /// page
+    pub next_cursor: Option<String>,
+}
+
+/// Result of backup import
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize))]
+pub struct BackupImport {
+    pub categories: ImportCount,
+    pub favourites: ImportCount,
+    pub history: ImportCount,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[cfg_attr(test, derive(serde::Deserialize, PartialEq))]
+pub struct ImportCount {
+    /// Valid entries. They are merged like in sync, so ones older than stored
+    /// or deleted items are not applied
+    pub parsed: usize,
+    /// Invalid entries, or favourites without category
+    pub skipped: usize,
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{config::ConfServerRateLimit, models::admin::RateLimitStats};
+
+/// When there are more entries, stale ones are removed
+const PRUNE_THRESHOLD: usize = 1000;
+
+/// Limits failed login attempts per email and per client IP in a sliding
+/// window. When limit is reached, key is locked, and each lockout in a row
+/// doubles lockout time
+pub struct RateLimiter {
+    conf: ConfServerRateLimit,
+    entries: Mutex<HashMap<Key, Entry>>,
+    failed_attempts: AtomicU64,
+    blocked_requests: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Email(String),
+    Ip(IpAddr),
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    failures: VecDeque<Instant>,
+    locked_until: Option<Instant>,
+    /// Number of lockouts in a row
+    lockouts: u32,
+}
+
+impl RateLimiter {
+    pub fn new(conf: ConfServerRateLimit) -> Self {
+        Self {
+            conf,
+            entries: Mutex::default(),
+            failed_attempts: AtomicU64::new(0),
+            blocked_requests: AtomicU64::new(0),
+        }
+    }
+    /// Check if login is allowed. Returns time after which request can be
+    /// retried, if not
+    pub fn check(&self, email: &str, ip: Option<IpAddr>) -> Result<(), Duration> {
+        if !self.conf.enabled {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let retry_after = Self::keys(email, ip)
+            .filter_map(|(key, _)| entries.get(&key)?.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+            .max();
+
+        match retry_after {
+            Some(retry_after) => {
+                self.blocked_requests.fetch_add(1, Ordering::Relaxed);
+                Err(retry_after)
+            }
+            None => Ok(()),
+        }
+    }
+    /// Register failed login attempt
+    pub fn failure(&self, email: &str, ip: Option<IpAddr>) {
+        if !self.conf.enabled {
+            return;
+        }
+        self.failed_attempts.fetch_add(1, Ordering::Relaxed);
+
+        let now = Instant::now();
+        let window = Duration::from_secs(self.conf.window);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+
+        for (key, is_email) in Self::keys(email, ip) {
+            let max = match is_email {
+                true => self.conf.max_per_email,
+                false => self.conf.max_per_ip,
+            };
+            let entry = entries.entry(key).or_default();
+            entry.failures.push_back(now);
+            while entry
+                .failures
+                .front()
+                .is_some_and(|t| now.duration_since(*t) > window)
+            {
+                entry.failures.pop_front();
+            }
+
+            if entry.failures.len() >= max as usize {
+                entry.failures.clear();
+                entry.lockouts += 1;
+                entry.locked_until = Some(now + self.lockout(entry.lockouts));
+            }
+        }
+
+        if entries.len() > PRUNE_THRESHOLD {
+            entries.retain(|_, e| !e.is_stale(now, window));
+        }
+    }
+    /// Register successful login, which resets back-off for email
+    pub fn success(&self, email: &str) {
+        if !self.conf.enabled {
+            return;
+        }
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&Key::Email(email.to_string()));
+    }
+    pub fn stats(&self) -> RateLimitStats {
+        let now = Instant::now();
+        let locked = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .filter(|e| e.locked_until.is_some_and(|until| until > now))
+            .count();
+        RateLimitStats {
+            failed_attempts: self.failed_attempts.load(Ordering::Relaxed),
+            blocked_requests: self.blocked_requests.load(Ordering::Relaxed),
+            locked_keys: locked as u64,
+        }
+    }
+    fn lockout(&self, lockouts: u32) -> Duration {
+        let factor = 2u64.saturating_pow(lockouts.saturating_sub(1));
+        Duration::from_secs(
+            self.conf
+                .lockout
+                .saturating_mul(factor)
+                .min(self.conf.max_lockout),
+        )
+    }
+    /// Returns keys with flag if key is email
+    fn keys(email: &str, ip: Option<IpAddr>) -> impl Iterator<Item = (Key, bool)> {
+        std::iter::once((Key::Email(email.to_string()), true))
+            .chain(ip.map(|ip| (Key::Ip(ip), false)))
+    }
+}
+
+impl Entry {
+    /// Entry is stale when it has no recent failures and lockout ended more
+    /// than a window ago
+    fn is_stale(&self, now: Instant, window: Duration) -> bool {
+        self.failures
+            .back()
+            .is_none_or(|t| now.duration_since(*t) > window)
+            && self.locked_until.is_none_or(|until| until + window < now)
+    }
+}
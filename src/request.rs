@@ -3,7 +3,12 @@ use rocket::{
     request::{FromRequest, Outcome, Request},
 };
 
-use crate::{jwt::validate, models::common::UserID};
+use crate::{
+    config::Conf,
+    db::conn::DB,
+    jwt::validate,
+    models::common::{SessionID, UserID},
+};
 
 /// When added to request handler arguments, performs validation of Bearer token
 ///
@@ -21,14 +26,27 @@ use crate::{jwt::validate, models::common::UserID};
 #[derive(Debug)]
 pub struct ApiToken {
     pub user_id: UserID,
+    pub session_id: SessionID,
 }
 
 #[derive(Debug, thiserror::Error)]
+#[error("{}", self.message())]
 pub enum AuthError {
-    #[error("authorization missing")]
     MissingAuthorization,
-    #[error("invalid token")]
     InvalidToken,
+    RevokedSession,
+    SessionCheck,
+}
+
+impl AuthError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::MissingAuthorization => "authorization missing",
+            Self::InvalidToken => "invalid token",
+            Self::RevokedSession => "session revoked",
+            Self::SessionCheck => "failed to check session",
+        }
+    }
 }
 
 #[rocket::async_trait]
@@ -36,12 +54,78 @@ impl<'r> FromRequest<'r> for ApiToken {
     type Error = AuthError;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        match req.headers().get_one("authorization") {
-            Some(key) => match validate(key.trim_start_matches("Bearer ")) {
-                Ok(user_id) => Outcome::Success(ApiToken { user_id }),
-                Err(_) => Outcome::Error((Status::Unauthorized, Self::Error::InvalidToken)),
-            },
-            None => Outcome::Error((Status::Unauthorized, Self::Error::MissingAuthorization)),
+        let Some(key) = req.headers().get_one("authorization") else {
+            return Outcome::Error((Status::Unauthorized, Self::Error::MissingAuthorization));
+        };
+        let Ok(claims) = validate(key.trim_start_matches("Bearer ")) else {
+            return Outcome::Error((Status::Unauthorized, Self::Error::InvalidToken));
+        };
+        let Some(db) = req.rocket().state::<DB>() else {
+            log::error!("failed to get db from state");
+            return Outcome::Error((Status::InternalServerError, Self::Error::SessionCheck));
+        };
+
+        match db.is_session_active(claims.sid, claims.user_id) {
+            Ok(true) => {
+                if let Err(e) = db.touch_session(claims.sid) {
+                    log::error!("failed to update session {}: {e}", claims.sid);
+                }
+                Outcome::Success(ApiToken {
+                    user_id: claims.user_id,
+                    session_id: claims.sid,
+                })
+            }
+            Ok(false) => Outcome::Error((Status::Unauthorized, Self::Error::RevokedSession)),
+            Err(e) => {
+                log::error!("failed to check session {}: {e}", claims.sid);
+                Outcome::Error((Status::InternalServerError, Self::Error::SessionCheck))
+            }
+        }
+    }
+}
+
+/// Value of User-Agent header, if present
+#[derive(Debug)]
+pub struct UserAgent(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UserAgent {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(UserAgent(
+            req.headers().get_one("user-agent").map(|s| s.to_string()),
+        ))
+    }
+}
+
+/// When added to request handler arguments, checks that Bearer token is equal
+/// to configured admin token
+#[derive(Debug)]
+pub struct AdminToken;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminToken {
+    type Error = AuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(key) = req.headers().get_one("authorization") else {
+            return Outcome::Error((Status::Unauthorized, Self::Error::MissingAuthorization));
+        };
+        let Some(admin_token) = req
+            .rocket()
+            .state::<Conf>()
+            .and_then(|c| c.server.admin_token.as_deref())
+            .filter(|t| !t.is_empty())
+        else {
+            return Outcome::Error((Status::Unauthorized, Self::Error::InvalidToken));
+        };
+
+        // blake3::Hash comparison is constant-time
+        let token = key.trim_start_matches("Bearer ");
+        match blake3::hash(token.as_bytes()) == blake3::hash(admin_token.as_bytes()) {
+            true => Outcome::Success(AdminToken),
+            false => Outcome::Error((Status::Unauthorized, Self::Error::InvalidToken)),
         }
     }
 }
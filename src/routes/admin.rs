@@ -1,24 +1,263 @@
-use rocket::{State, get, http::Status, serde::json::Json};
+use rocket::{State, delete, get, http::Status, post, serde::json::Json};
 
-use crate::{db::conn::DB, models::admin};
+use crate::{
+    config::Conf,
+    current_timestamp,
+    db::conn::DB,
+    gc, jwt,
+    models::{
+        admin,
+        common::UserID,
+        db::{InviteInsert, PasswordResetInsert},
+    },
+    random_string,
+    ratelimit::RateLimiter,
+    request::AdminToken,
+    scheduler::Scheduler,
+    snapshot,
+};
 
 use super::{Response, ResponseData};
 
 const SERVER_VERSION: &str = env!("VERSION");
+const INVITE_CODE_LEN: usize = 16;
+const RESET_TOKEN_LEN: usize = 32;
+/// 1 day
+const RESET_TOKEN_LIFETIME_SEC: u64 = 24 * 60 * 60;
+const USERS_DEFAULT_LIMIT: usize = 100;
+const USERS_MAX_LIMIT: usize = 1000;
 
 #[get("/stats")]
-pub fn stats(db: &State<DB>) -> Response<Json<admin::DBStats>> {
+pub fn stats(
+    _admin: AdminToken,
+    config: &State<Conf>,
+    db: &State<DB>,
+    limiter: &State<RateLimiter>,
+) -> Response<Json<admin::Stats>> {
     let stats = db.stats().map_err(|e| {
         log::error!("failed to load stats: {e}");
         ResponseData::Status(Status::InternalServerError)
     })?;
+    let last_backup_at = snapshot::last(&config.backup).map_err(|e| {
+        log::error!("failed to list backups: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
 
-    Ok(Json(stats).into())
+    Ok(Json(admin::Stats {
+        db: stats,
+        rate_limit: limiter.stats(),
+        last_backup_at,
+    })
+    .into())
 }
 
 #[get("/info")]
-pub fn info() -> Json<admin::ServerInfo> {
+pub fn info(_admin: AdminToken) -> Json<admin::ServerInfo> {
     Json(admin::ServerInfo {
         server_version: SERVER_VERSION.to_string(),
     })
 }
+
+#[post("/invites", data = "<req>")]
+pub fn create_invite(
+    _admin: AdminToken,
+    req: Json<admin::CreateInvite>,
+    db: &State<DB>,
+) -> Response<Json<admin::Invite>, &'static str> {
+    if req.max_uses < 1 {
+        return Err((Status::BadRequest, "max_uses should be at least 1").into());
+    }
+
+    let now = current_timestamp().unwrap_or_default();
+    let invite = db
+        .create_invite(InviteInsert {
+            code: random_string(INVITE_CODE_LEN),
+            max_uses: req.max_uses,
+            created_at: now,
+            expires_at: req.expires_in.map(|secs| now + secs as i64 * 1000),
+        })
+        .map_err(|e| {
+            log::error!("failed to create invite: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?;
+
+    Ok(Json(invite.to_api()).into())
+}
+
+#[get("/invites")]
+pub fn list_invites(_admin: AdminToken, db: &State<DB>) -> Response<Json<Vec<admin::Invite>>> {
+    let invites = db.list_invites().map_err(|e| {
+        log::error!("failed to list invites: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+
+    let invites: Vec<_> = invites.iter().map(|i| i.to_api()).collect();
+    Ok(Json(invites).into())
+}
+
+/// Issue single-use token for resetting user's password
+#[post("/users/<id>/password-reset?<expires_in>")]
+pub fn create_password_reset(
+    _admin: AdminToken,
+    id: UserID,
+    expires_in: Option<u64>,
+    db: &State<DB>,
+) -> Response<Json<admin::PasswordReset>> {
+    let user = db.get_user(id).map_err(|e| {
+        log::error!("failed to get user {id}: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    if user.is_none() {
+        return Err(ResponseData::Status(Status::NotFound));
+    }
+
+    let token = random_string(RESET_TOKEN_LEN);
+    let now = current_timestamp().unwrap_or_default();
+    let expires_at = now + expires_in.unwrap_or(RESET_TOKEN_LIFETIME_SEC) as i64 * 1000;
+    db.create_password_reset(PasswordResetInsert {
+        user_id: id,
+        token_hash: jwt::hash_token(&token),
+        created_at: now,
+        expires_at,
+    })
+    .map_err(|e| {
+        log::error!("failed to create password reset for user {id}: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+
+    Ok(Json(admin::PasswordReset {
+        user_id: id,
+        token,
+        expires_at,
+    })
+    .into())
+}
+
+#[get("/users?<offset>&<limit>")]
+pub fn list_users(
+    _admin: AdminToken,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    db: &State<DB>,
+) -> Response<Json<Vec<admin::User>>, &'static str> {
+    let limit = limit.unwrap_or(USERS_DEFAULT_LIMIT);
+    if limit > USERS_MAX_LIMIT {
+        return Err((Status::BadRequest, "max limit is 1000").into());
+    }
+
+    let users = db.list_users(offset.unwrap_or(0), limit).map_err(|e| {
+        log::error!("failed to list users: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    Ok(Json(users).into())
+}
+
+#[get("/users/<id>")]
+pub fn get_user(_admin: AdminToken, id: UserID, db: &State<DB>) -> Response<Json<admin::User>> {
+    let user = db.get_user_info(id).map_err(|e| {
+        log::error!("failed to get user {id}: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    match user {
+        Some(user) => Ok(Json(user).into()),
+        None => Err(ResponseData::Status(Status::NotFound)),
+    }
+}
+
+/// Disable user and revoke all sessions. Disabled user can't log in
+#[post("/users/<id>/disable")]
+pub fn disable_user(_admin: AdminToken, id: UserID, db: &State<DB>) -> Response<()> {
+    set_user_disabled(id, true, db)
+}
+
+#[post("/users/<id>/enable")]
+pub fn enable_user(_admin: AdminToken, id: UserID, db: &State<DB>) -> Response<()> {
+    set_user_disabled(id, false, db)
+}
+
+/// Delete user with all data
+#[delete("/users/<id>")]
+pub fn delete_user(_admin: AdminToken, id: UserID, db: &State<DB>) -> Response<()> {
+    let user = db.get_user(id).map_err(|e| {
+        log::error!("failed to get user {id}: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    if user.is_none() {
+        return Err(ResponseData::Status(Status::NotFound));
+    }
+
+    db.delete_user(id).map_err(|e| {
+        log::error!("failed to delete user {id}: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    Ok(ResponseData::Status(Status::NoContent))
+}
+
+fn set_user_disabled(id: UserID, disabled: bool, db: &State<DB>) -> Response<()> {
+    let found = db.set_user_disabled(id, disabled).map_err(|e| {
+        log::error!("failed to update user {id}: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    match found {
+        true => Ok(ResponseData::Status(Status::NoContent)),
+        false => Err(ResponseData::Status(Status::NotFound)),
+    }
+}
+
+/// Remove old soft-deleted rows and unreferenced manga now
+#[post("/gc")]
+pub fn run_gc(
+    _admin: AdminToken,
+    config: &State<Conf>,
+    db: &State<DB>,
+) -> Response<Json<admin::GCStats>> {
+    let stats = gc::run(db, &config.gc).map_err(|e| {
+        log::error!("gc failed: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    Ok(Json(stats).into())
+}
+
+/// Make backup of SQLite database now
+#[post("/backup")]
+pub fn run_backup(
+    _admin: AdminToken,
+    config: &State<Conf>,
+    db: &State<DB>,
+) -> Response<Json<admin::Backup>> {
+    if !snapshot::enabled(&config.backup) {
+        return Err((Status::BadRequest, "backups are disabled".to_string()).into());
+    }
+    let backup = snapshot::run(db, &config.backup).map_err(|e| {
+        log::error!("backup failed: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    Ok(Json(backup).into())
+}
+
+#[get("/jobs")]
+pub fn list_jobs(_admin: AdminToken, scheduler: &State<Scheduler>) -> Json<Vec<admin::JobStatus>> {
+    Json(scheduler.statuses())
+}
+
+/// Run background job now and wait for it to finish
+#[post("/jobs/<name>")]
+pub fn run_job(
+    _admin: AdminToken,
+    name: &str,
+    scheduler: &State<Scheduler>,
+    db: &State<DB>,
+) -> Response<Json<admin::JobStatus>> {
+    match scheduler.run(name, db) {
+        None => Err(ResponseData::Status(Status::NotFound)),
+        Some(Err(e)) => Err((Status::InternalServerError, e.to_string()).into()),
+        Some(Ok(())) => {
+            let status = scheduler
+                .statuses()
+                .into_iter()
+                .find(|s| s.name == name)
+                .ok_or(ResponseData::Status(Status::NotFound))?;
+            Ok(Json(status).into())
+        }
+    }
+}
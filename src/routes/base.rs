@@ -1,18 +1,32 @@
+use std::{net::IpAddr, time::Duration};
+
 use anyhow::Result;
-use rocket::{State, get, http::Status, post, response::status::Custom, serde::json::Json};
+use rocket::{
+    Responder, State, delete, get,
+    http::{Header, Status},
+    post,
+    response::status::Custom,
+    serde::json::Json,
+};
 
 use crate::{
+    backup,
     config::Conf,
+    current_timestamp,
     db::conn::DB,
     jwt,
-    models::{common, request, response},
-    request::{ApiToken, AuthError},
+    models::{
+        common::{self, SessionID, UserID},
+        db::{Manga, SessionInsert, Tag, User},
+        request,
+        request::PasswordCheck,
+        response,
+    },
+    ratelimit::RateLimiter,
+    request::{ApiToken, AuthError, UserAgent},
 };
 
-#[cfg(feature = "migrate-md5")]
-use crate::models::request::MD5_LEN;
-
-use super::{Response, ResponseData, user_by_token};
+use super::{Response, ResponseData, ResponseErr, token_or_unauthorized, user_by_token};
 
 #[get("/<_..>")]
 pub fn fallback() -> Status {
@@ -27,53 +41,157 @@ pub fn root() -> &'static str {
 #[post("/auth", data = "<req>")]
 pub fn auth(
     req: Json<request::Auth>,
+    user_agent: UserAgent,
+    ip: Option<IpAddr>,
     config: &State<Conf>,
     db: &State<DB>,
+    limiter: &State<RateLimiter>,
 ) -> Response<Json<response::Auth>, &'static str> {
     let req = req
         .0
         .parse()
         .map_err(|e| ResponseData::StatusMessage(Custom(Status::BadRequest, e)))?;
 
+    limiter.check(&req.email, ip).map_err(|retry_after| {
+        log::warn!("too many login attempts for {}", req.email);
+        too_many_requests("Too many login attempts", retry_after)
+    })?;
+
     log::debug!("getting user");
     let user = db.get_user_by_email(&req.email).map_err(|e| {
         log::error!("failed to get user: {e}");
         ResponseData::Status(Status::InternalServerError)
     })?;
     let user = match user {
-        Some(u) if req.check_password(&u).is_err() => {
-            // todo: wait for 2s (configurable)
-            return Err((Status::BadRequest, "Wrong password").into());
-        }
-        #[cfg(feature = "migrate-md5")]
-        Some(u) if u.password_hash.len() == MD5_LEN => {
-            match db.update_user_password(u.id, &req.password) {
-                Ok(()) => (),
-                Err(e) => log::error!("failed to update user password: {e}"),
+        Some(u) => match req.check_password(&u) {
+            Err(()) => {
+                limiter.failure(&req.email, ip);
+                return Err((Status::BadRequest, "Wrong password").into());
             }
-            u
-        }
-        Some(u) => u,
-        None => {
-            if !config.server.allow_new_register {
-                return Err((Status::Forbidden, "registration of new users is disabled").into());
+            Ok(PasswordCheck::Outdated) => {
+                log::debug!("updating password hash for user {}", u.id);
+                match req
+                    .hash_password()
+                    .and_then(|hash| db.update_user_password(u.id, &hash))
+                {
+                    Ok(()) => (),
+                    Err(e) => log::error!("failed to update user password: {e}"),
+                }
+                u
             }
-            log::debug!("creating user");
-            db.create_user(&req.email, &req.password).map_err(|e| {
-                log::error!("failed to save user: {e}");
-                ResponseData::Status(Status::InternalServerError)
-            })?
+            Ok(PasswordCheck::Valid) => u,
+        },
+        // the same response as for wrong password, to not reveal which
+        // emails are registered
+        None if config.server.separate_register => {
+            request::check_password_dummy(&req.password);
+            limiter.failure(&req.email, ip);
+            return Err((Status::BadRequest, "Wrong password").into());
         }
+        None => register_user(&req, config, db)?,
     };
 
-    let token = jwt::generate(user.id, &config.jwt).map_err(|e| {
-        log::error!("failed to generate jwt: {e}");
-        ResponseData::StatusMessage(Custom(
-            Status::InternalServerError,
-            "failed to generate token",
-        ))
+    if user.disabled_at.is_some() {
+        return Err((Status::Forbidden, "User is disabled").into());
+    }
+
+    limiter.success(&req.email);
+
+    Ok(Json(start_session(&user, &req, user_agent, config, db)?).into())
+}
+
+#[post("/auth/reset", data = "<req>")]
+pub fn reset_password(
+    req: Json<request::ResetPassword>,
+    db: &State<DB>,
+) -> Response<(), &'static str> {
+    let req = req
+        .0
+        .parse()
+        .map_err(|e| ResponseData::StatusMessage(Custom(Status::BadRequest, e)))?;
+
+    let password_hash = request::hash_password(&req.new_password).map_err(|e| {
+        log::error!("{e}");
+        ResponseData::Status(Status::InternalServerError)
     })?;
-    Ok(Json(response::Auth { token }).into())
+    let user_id = db
+        .reset_password(&jwt::hash_token(&req.token), &password_hash)
+        .map_err(|e| {
+            log::error!("failed to reset password: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?
+        .ok_or((Status::BadRequest, "Invalid reset token"))?;
+    log::info!("password reset for user {user_id}");
+
+    Ok(ResponseData::Status(Status::NoContent))
+}
+
+#[post("/register", data = "<req>")]
+pub fn register(
+    req: Json<request::Auth>,
+    user_agent: UserAgent,
+    config: &State<Conf>,
+    db: &State<DB>,
+) -> Response<Json<response::Auth>, &'static str> {
+    let req = req
+        .0
+        .parse()
+        .map_err(|e| ResponseData::StatusMessage(Custom(Status::BadRequest, e)))?;
+
+    let exists = db
+        .get_user_by_email(&req.email)
+        .map_err(|e| {
+            log::error!("failed to get user: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?
+        .is_some();
+    if exists {
+        return Err((Status::Conflict, "User already exists").into());
+    }
+
+    let user = register_user(&req, config, db)?;
+    Ok(Json(start_session(&user, &req, user_agent, config, db)?).into())
+}
+
+#[post("/auth/refresh", data = "<req>")]
+pub fn refresh(
+    req: Json<request::Refresh>,
+    config: &State<Conf>,
+    db: &State<DB>,
+) -> Response<Json<response::Auth>, &'static str> {
+    let refresh_token = jwt::generate_refresh();
+    let session = db
+        .rotate_refresh_token(
+            &jwt::hash_token(&req.refresh_token),
+            &jwt::hash_token(&refresh_token),
+            jwt::refresh_expires_at(&config.jwt),
+        )
+        .map_err(|e| {
+            log::error!("failed to rotate refresh token: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?
+        .ok_or((Status::Unauthorized, "invalid refresh token"))?;
+
+    let token = generate_token(session.user_id, session.id, config)?;
+    Ok(Json(response::Auth {
+        token,
+        refresh_token,
+    })
+    .into())
+}
+
+#[post("/auth/logout", data = "<req>")]
+pub fn logout(req: Json<request::Refresh>, db: &State<DB>) -> Response<(), &'static str> {
+    let found = db
+        .revoke_session_by_refresh_token(&jwt::hash_token(&req.refresh_token))
+        .map_err(|e| {
+            log::error!("failed to revoke session: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?;
+    match found {
+        true => Ok(ResponseData::Status(Status::NoContent)),
+        false => Err((Status::Unauthorized, "invalid refresh token").into()),
+    }
 }
 
 #[get("/me")]
@@ -87,6 +205,99 @@ pub fn me(token: Result<ApiToken, AuthError>, db: &State<DB>) -> Response<Json<r
     .into())
 }
 
+/// Backup zip with content type and file name
+#[derive(Responder)]
+#[response(content_type = "application/zip")]
+pub struct Export(Vec<u8>, Header<'static>);
+
+/// Export favourites and history as Kotatsu app backup
+#[get("/me/export")]
+pub fn export(token: Result<ApiToken, AuthError>, db: &State<DB>) -> Response<Export> {
+    let user = user_by_token(token, db)?;
+    let now = current_timestamp().unwrap_or_default();
+
+    let favourites = db.load_favourites_package(user.id, None).map_err(|e| {
+        log::error!(
+            "failed to load favourites_package for user {}: {e}",
+            user.id
+        );
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    let history = db.load_history_package(user.id, None).map_err(|e| {
+        log::error!("failed to load history_package for user {}: {e}", user.id);
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    let archive = backup::write(&favourites, &history, now).map_err(|e| {
+        log::error!("failed to write backup for user {}: {e}", user.id);
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+
+    Ok(Export(
+        archive,
+        Header::new(
+            "Content-Disposition",
+            "attachment; filename=\"kotync.bk.zip\"",
+        ),
+    )
+    .into())
+}
+
+#[post("/me/password", data = "<req>")]
+pub fn change_password(
+    req: Json<request::ChangePassword>,
+    token: Result<ApiToken, AuthError>,
+    ip: Option<IpAddr>,
+    db: &State<DB>,
+    limiter: &State<RateLimiter>,
+) -> Response<(), &'static str> {
+    let token = token_or_unauthorized(token)?;
+    let session_id = token.session_id;
+    let user = user_by_token(Ok(token), db)?;
+    let req = req
+        .0
+        .parse()
+        .map_err(|e| ResponseData::StatusMessage(Custom(Status::BadRequest, e)))?;
+
+    // the same limit as for login, so stolen token can't be used to guess
+    // password
+    limiter.check(&user.email, ip).map_err(|retry_after| {
+        log::warn!("too many password change attempts for {}", user.email);
+        too_many_requests("Too many password attempts", retry_after)
+    })?;
+
+    if request::check_password(&req.current_password, &user.password_hash).is_err() {
+        limiter.failure(&user.email, ip);
+        return Err((Status::BadRequest, "Wrong password").into());
+    }
+    limiter.success(&user.email);
+
+    request::hash_password(&req.new_password)
+        .and_then(|hash| db.update_user_password(user.id, &hash))
+        .map_err(|e| {
+            log::error!("failed to update password for user {}: {e}", user.id);
+            ResponseData::Status(Status::InternalServerError)
+        })?;
+
+    db.revoke_other_sessions(user.id, session_id).map_err(|e| {
+        log::error!("failed to revoke sessions for user {}: {e}", user.id);
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+
+    Ok(ResponseData::Status(Status::NoContent))
+}
+
+#[delete("/me")]
+pub fn delete_me(token: Result<ApiToken, AuthError>, db: &State<DB>) -> Response<()> {
+    let user = user_by_token(token, db)?;
+    db.delete_user(user.id).map_err(|e| {
+        log::error!("failed to delete user {}: {e}", user.id);
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    log::info!("deleted user {}", user.id);
+
+    Ok(ResponseData::Status(Status::NoContent))
+}
+
 #[get("/manga/<id>")]
 pub fn get_manga(id: i64, db: &State<DB>) -> Response<Option<Json<common::Manga>>> {
     let manga = db.get_manga(id).map_err(|e| {
@@ -98,30 +309,302 @@ pub fn get_manga(id: i64, db: &State<DB>) -> Response<Option<Json<common::Manga>
         .into())
 }
 
-#[get("/manga?<offset>&<limit>")]
+/// Full-text search of manga by title and alt title, best matches first
+#[get("/manga/search?<q>&<limit>")]
+pub fn search_manga(
+    q: Option<&str>,
+    limit: Option<usize>,
+    db: &State<DB>,
+) -> Response<Json<Vec<common::Manga>>, &'static str> {
+    let Some(q) = q.filter(|q| !q.trim().is_empty()) else {
+        return Err((Status::BadRequest, "q is required").into());
+    };
+    let limit = limit.unwrap_or(100);
+    if limit > 1000 {
+        return Err((Status::BadRequest, "max limit is 1000").into());
+    }
+
+    let list: Vec<_> = db
+        .search_manga(q, limit)
+        .map_err(|e| {
+            log::error!("failed to search manga: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?
+        .into_iter()
+        .map(|(manga, tags)| manga.to_api(tags.into_iter().map(|t| t.to_api()).collect()))
+        .collect();
+    Ok(Json(list).into())
+}
+
+/// Manga list for offset pagination, or page for cursor pagination
+#[derive(Responder)]
+pub enum MangaList {
+    List(Json<Vec<common::Manga>>),
+    Page(Json<response::MangaPage>),
+}
+
+/// List manga ordered by id. Either `offset` or `cursor` is required.
+///
+/// With `cursor`, page with `next_cursor` is returned instead of list. Empty
+/// `cursor` requests the first page. Cursor should be used with the same
+/// filters
+#[get("/manga?<offset>&<limit>&<cursor>&<filter..>")]
 pub fn list_manga(
     offset: Option<usize>,
     limit: Option<usize>,
+    cursor: Option<&str>,
+    filter: request::MangaFilter,
     db: &State<DB>,
-) -> Response<Json<Vec<common::Manga>>, &'static str> {
-    let Some(offset) = offset else {
+) -> Response<MangaList, &'static str> {
+    if offset.is_none() && cursor.is_none() {
         return Err((Status::BadRequest, "offset is required").into());
-    };
+    }
+    if offset.is_some() && cursor.is_some() {
+        return Err((
+            Status::BadRequest,
+            "offset and cursor can't be used together",
+        )
+            .into());
+    }
     let Some(limit) = limit else {
         return Err((Status::BadRequest, "limit is required").into());
     };
     if limit > 1000 {
         return Err((Status::BadRequest, "max limit is 1000").into());
     }
+    if filter
+        .state
+        .as_deref()
+        .is_some_and(|s| s.parse::<common::MangaState>().is_err())
+    {
+        return Err((Status::BadRequest, "unknown state").into());
+    }
+    if filter
+        .content_rating
+        .as_deref()
+        .is_some_and(|r| r.parse::<common::ContentRating>().is_err())
+    {
+        return Err((Status::BadRequest, "unknown content_rating").into());
+    }
+    let to_api = |list: Vec<(Manga, Vec<Tag>)>| -> Vec<_> {
+        list.into_iter()
+            .map(|(manga, tags)| manga.to_api(tags.into_iter().map(|t| t.to_api()).collect()))
+            .collect()
+    };
 
-    let list: Vec<_> = db
-        .list_manga(offset, limit)
+    let Some(cursor) = cursor else {
+        let list = db
+            .list_manga(offset.unwrap_or_default(), limit, &filter)
+            .map_err(|e| {
+                log::error!("failed to list manga: {e}");
+                ResponseData::Status(Status::InternalServerError)
+            })?;
+        return Ok(MangaList::List(Json(to_api(list))).into());
+    };
+
+    // cursor is id of the last manga on previous page
+    let after = match cursor {
+        "" => None,
+        cursor => Some(
+            cursor
+                .parse()
+                .map_err(|_| ResponseData::from((Status::BadRequest, "invalid cursor")))?,
+        ),
+    };
+    // one more to know if there is next page
+    let mut list = db
+        .list_manga_after(after, limit + 1, &filter)
         .map_err(|e| {
             log::error!("failed to list manga: {e}");
             ResponseData::Status(Status::InternalServerError)
+        })?;
+    let next_cursor = match list.len() > limit {
+        true => {
+            list.truncate(limit);
+            list.last().map(|(m, _)| m.id.to_string())
+        }
+        false => None,
+    };
+    Ok(MangaList::Page(Json(response::MangaPage {
+        manga: to_api(list),
+        next_cursor,
+    }))
+    .into())
+}
+
+/// List tags ordered by id
+#[get("/tags?<source>&<offset>&<limit>")]
+pub fn list_tags(
+    source: Option<&str>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    db: &State<DB>,
+) -> Response<Json<Vec<common::MangaTag>>, &'static str> {
+    let limit = limit.unwrap_or(100);
+    if limit > 1000 {
+        return Err((Status::BadRequest, "max limit is 1000").into());
+    }
+
+    let tags = db
+        .list_tags(source, offset.unwrap_or_default(), limit)
+        .map_err(|e| {
+            log::error!("failed to list tags: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?;
+    Ok(Json(tags.iter().map(|t| t.to_api()).collect::<Vec<_>>()).into())
+}
+
+#[get("/tags/<id>")]
+pub fn get_tag(id: i64, db: &State<DB>) -> Response<Option<Json<common::MangaTag>>> {
+    let tag = db.get_tag(id).map_err(|e| {
+        log::error!("failed to get tag {id}: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    Ok(tag.map(|t| Json(t.to_api())).into())
+}
+
+/// List manga with tag, ordered by id
+#[get("/tags/<id>/manga?<offset>&<limit>")]
+pub fn list_tag_manga(
+    id: i64,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    db: &State<DB>,
+) -> Response<Option<Json<Vec<common::Manga>>>, &'static str> {
+    let limit = limit.unwrap_or(100);
+    if limit > 1000 {
+        return Err((Status::BadRequest, "max limit is 1000").into());
+    }
+
+    let tag = db.get_tag(id).map_err(|e| {
+        log::error!("failed to get tag {id}: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    if tag.is_none() {
+        return Ok(None.into());
+    }
+
+    let list: Vec<_> = db
+        .list_tag_manga(id, offset.unwrap_or_default(), limit)
+        .map_err(|e| {
+            log::error!("failed to list manga with tag {id}: {e}");
+            ResponseData::Status(Status::InternalServerError)
         })?
         .into_iter()
         .map(|(manga, tags)| manga.to_api(tags.into_iter().map(|t| t.to_api()).collect()))
         .collect();
-    Ok(Json(list).into())
+    Ok(Some(Json(list)).into())
+}
+
+#[get("/sessions")]
+pub fn list_sessions(
+    token: Result<ApiToken, AuthError>,
+    db: &State<DB>,
+) -> Response<Json<Vec<response::Session>>> {
+    let token = token_or_unauthorized(token)?;
+    let sessions = db.list_sessions(token.user_id).map_err(|e| {
+        log::error!("failed to list sessions for user {}: {e}", token.user_id);
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    let sessions: Vec<_> = sessions
+        .iter()
+        .map(|s| s.to_api(token.session_id))
+        .collect();
+    Ok(Json(sessions).into())
+}
+
+#[delete("/sessions/<id>")]
+pub fn delete_session(
+    id: SessionID,
+    token: Result<ApiToken, AuthError>,
+    db: &State<DB>,
+) -> Response<()> {
+    let token = token_or_unauthorized(token)?;
+    let revoked = db.revoke_session(token.user_id, id).map_err(|e| {
+        log::error!("failed to revoke session {id}: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    match revoked {
+        true => Ok(ResponseData::Status(Status::NoContent)),
+        false => Err(ResponseData::Status(Status::NotFound)),
+    }
+}
+
+/// Create user, if registration is allowed or valid invite code is provided
+fn register_user(req: &request::Auth, config: &Conf, db: &DB) -> ResponseErr<User, &'static str> {
+    let invite = match &req.invite {
+        _ if config.server.allow_new_register => None,
+        Some(invite) => Some(invite),
+        None => {
+            return Err((Status::Forbidden, "registration of new users is disabled").into());
+        }
+    };
+
+    let password_hash = req.hash_password().map_err(|e| {
+        log::error!("{e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+    log::debug!("creating user");
+    let user = match invite {
+        Some(invite) => db.create_user_with_invite(&req.email, &password_hash, invite),
+        None => db.create_user(&req.email, &password_hash).map(Some),
+    }
+    .map_err(|e| {
+        log::error!("failed to save user: {e}");
+        ResponseData::Status(Status::InternalServerError)
+    })?;
+
+    user.ok_or((Status::Forbidden, "Invalid invite code").into())
+}
+
+fn too_many_requests(message: &'static str, retry_after: Duration) -> ResponseData<&'static str> {
+    ResponseData::StatusMessageHeader(
+        Custom(Status::TooManyRequests, message),
+        Header::new(
+            "Retry-After",
+            // round up to not allow retry before lockout ends
+            (retry_after.as_secs() + 1).to_string(),
+        ),
+    )
+}
+
+/// Create new session and generate tokens for it
+fn start_session(
+    user: &User,
+    req: &request::Auth,
+    user_agent: UserAgent,
+    config: &Conf,
+    db: &DB,
+) -> ResponseErr<response::Auth, &'static str> {
+    let refresh_token = jwt::generate_refresh();
+    let session_id = db
+        .create_session(
+            SessionInsert::new(user.id, req.device_name.clone(), user_agent.0),
+            &jwt::hash_token(&refresh_token),
+            jwt::refresh_expires_at(&config.jwt),
+        )
+        .map_err(|e| {
+            log::error!("failed to create session: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?;
+
+    let token = generate_token(user.id, session_id, config)?;
+    Ok(response::Auth {
+        token,
+        refresh_token,
+    })
+}
+
+fn generate_token(
+    user_id: UserID,
+    session_id: SessionID,
+    config: &Conf,
+) -> ResponseErr<String, &'static str> {
+    jwt::generate(user_id, session_id, &config.jwt).map_err(|e| {
+        log::error!("failed to generate jwt: {e}");
+        ResponseData::StatusMessage(Custom(
+            Status::InternalServerError,
+            "failed to generate token",
+        ))
+    })
 }
@@ -1,4 +1,8 @@
-use rocket::{Responder, State, http::Status, response::status::Custom};
+use rocket::{
+    Responder, State,
+    http::{Header, Status},
+    response::status::Custom,
+};
 
 use crate::{
     db::conn::DB,
@@ -18,11 +22,26 @@ pub enum ResponseData<R> {
     Body(R),
     Status(Status),
     StatusMessage(Custom<R>),
+    StatusMessageHeader(Custom<R>, Header<'static>),
+}
+
+fn token_or_unauthorized<E: From<&'static str>>(
+    token: Result<ApiToken, AuthError>,
+) -> ResponseErr<ApiToken, E> {
+    token.map_err(|e| {
+        let status = match e {
+            AuthError::SessionCheck => Status::InternalServerError,
+            _ => Status::Unauthorized,
+        };
+        ResponseData::StatusMessage(Custom(status, e.message().into()))
+    })
 }
 
-fn user_by_token(token: Result<ApiToken, AuthError>, db: &State<DB>) -> ResponseErr<User> {
-    let token = token
-        .map_err(|e| ResponseData::StatusMessage(Custom(Status::Unauthorized, e.to_string())))?;
+fn user_by_token<E: From<&'static str>>(
+    token: Result<ApiToken, AuthError>,
+    db: &State<DB>,
+) -> ResponseErr<User, E> {
+    let token = token_or_unauthorized(token)?;
     db.get_user(token.user_id)
         .map_err(|e| {
             log::error!("failed to select user: {e}");
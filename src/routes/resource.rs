@@ -1,59 +1,109 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
-use rocket::{State, get, http::Status, post, serde::json::Json};
+use rocket::{
+    State, get,
+    http::{Header, Status},
+    post,
+    response::status::Custom,
+    serde::json::Json,
+};
 
 use crate::{
-    current_timestamp,
+    backup,
+    config::Conf,
     db::conn::DB,
-    models::common,
+    models::{
+        Versioned,
+        common::{self, Time},
+        response,
+    },
     request::{ApiToken, AuthError},
 };
 
 use super::{Response, ResponseData, user_by_token};
 
-#[post("/favourites", data = "<req>")]
+const SYNC_CONFLICT_HEADER: &str = "X-Sync-Conflict";
+
+/// Merge favourites and return them back. For each item, the one with newer
+/// `created_at` or `deleted_at` is kept.
+///
+/// With `delta=true`, request contains only items changed since its
+/// `timestamp`, and response contains only items changed on server since it
+/// (excluding sent ones) with new `timestamp` for the next sync.
+///
+/// If favourites were synchronized by other client after `timestamp`, items
+/// are merged and `X-Sync-Conflict` header is set. With `strict=true`, nothing
+/// is saved and 409 is returned instead
+#[post("/favourites?<delta>&<strict>", data = "<req>")]
 pub fn save_favourites(
     req: Json<common::FavouritesPackage>,
+    delta: Option<bool>,
+    strict: Option<bool>,
     token: Result<ApiToken, AuthError>,
     db: &State<DB>,
 ) -> Response<Json<common::FavouritesPackage>> {
     let user = user_by_token(token, db)?;
+    let strict = strict.unwrap_or(false);
 
-    db.add_favourites_package(&req.0, user.id).map_err(|e| {
-        log::error!("failed to add favourites package: {e}");
-        ResponseData::Status(Status::InternalServerError)
-    })?;
+    let conflict = db
+        .add_favourites_package(&req.0, user.id, strict)
+        .map_err(|e| {
+            log::error!("failed to add favourites package: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?;
+    if conflict && strict {
+        return Err(ResponseData::StatusMessage(Custom(
+            Status::Conflict,
+            "favourites were changed by other client".to_string(),
+        )));
+    }
 
-    db.set_favourites_synchronized(user.id, current_timestamp().unwrap_or_default())
+    let delta = delta.unwrap_or(false);
+    let mut data = db
+        .load_favourites_package(user.id, req.timestamp.filter(|_| delta))
         .map_err(|e| {
             log::error!(
-                "failed to set favourites_sync_timestamp for user {}: {e}",
+                "failed to load favourites_package for user {}: {e}",
                 user.id
             );
             ResponseData::Status(Status::InternalServerError)
         })?;
 
-    let data = db.load_favourites_package(user.id).map_err(|e| {
-        log::error!(
-            "failed to load favourites_package for user {}: {e}",
-            user.id
-        );
-        ResponseData::Status(Status::InternalServerError)
-    })?;
+    if delta {
+        // client already has items it sent, unless they were older than
+        // items on server
+        let categories: HashMap<_, _> =
+            req.categories.iter().map(|c| (c.id, c.version())).collect();
+        let favourites: HashMap<_, _> = req
+            .favourites
+            .iter()
+            .map(|f| ((f.manga_id, f.category_id), f.version()))
+            .collect();
+        data.categories
+            .retain(|c| categories.get(&c.id) != Some(&c.version()));
+        data.favourites
+            .retain(|f| favourites.get(&(f.manga_id, f.category_id)) != Some(&f.version()));
+        return Ok(with_conflict(Json(data), conflict));
+    }
 
     match req.0 == data {
         // is this real usecase?
         true => Ok(ResponseData::Status(Status::NoContent)),
-        false => Ok(Json(data).into()),
+        false => Ok(with_conflict(Json(data), conflict)),
     }
 }
 
-#[get("/favourites")]
+/// Get favourites. With `since`, only items changed after it are returned,
+/// with new `timestamp` for the next sync
+#[get("/favourites?<since>")]
 pub fn get_favourites(
+    since: Option<Time>,
     token: Result<ApiToken, AuthError>,
     db: &State<DB>,
 ) -> Response<Json<common::FavouritesPackage>> {
     let user = user_by_token(token, db)?;
-    let data = db.load_favourites_package(user.id).map_err(|e| {
+    let data = db.load_favourites_package(user.id, since).map_err(|e| {
         log::error!(
             "failed to load favourites_package for user {}: {e}",
             user.id
@@ -63,49 +113,114 @@ pub fn get_favourites(
     Ok(Json(data).into())
 }
 
-#[post("/history", data = "<req>")]
+/// Merge history and return it back. For each item, the one with newer
+/// `updated_at` or `deleted_at` is kept. `delta` and `strict` work like for
+/// favourites
+#[post("/history?<delta>&<strict>", data = "<req>")]
 pub fn save_history(
     req: Json<common::HistoryPackage>,
+    delta: Option<bool>,
+    strict: Option<bool>,
     token: Result<ApiToken, AuthError>,
     db: &State<DB>,
 ) -> Response<Json<common::HistoryPackage>> {
     let user = user_by_token(token, db)?;
+    let strict = strict.unwrap_or(false);
 
-    db.add_history_package(&req.0, user.id).map_err(|e| {
-        log::error!("failed to add history package: {e}");
-        ResponseData::Status(Status::InternalServerError)
-    })?;
+    let conflict = db
+        .add_history_package(&req.0, user.id, strict)
+        .map_err(|e| {
+            log::error!("failed to add history package: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?;
+    if conflict && strict {
+        return Err(ResponseData::StatusMessage(Custom(
+            Status::Conflict,
+            "history were changed by other client".to_string(),
+        )));
+    }
 
-    db.set_history_synchronized(user.id, current_timestamp().unwrap_or_default())
+    let delta = delta.unwrap_or(false);
+    let mut data = db
+        .load_history_package(user.id, req.timestamp.filter(|_| delta))
         .map_err(|e| {
-            log::error!(
-                "failed to set history_sync_timestamp for user {}: {e}",
-                user.id
-            );
+            log::error!("failed to load history_package for user {}: {e}", user.id);
             ResponseData::Status(Status::InternalServerError)
         })?;
 
-    let data = db.load_history_package(user.id).map_err(|e| {
-        log::error!("failed to load history_package for user {}: {e}", user.id);
-        ResponseData::Status(Status::InternalServerError)
-    })?;
+    if delta {
+        // client already has items it sent, unless they were older than
+        // items on server
+        let history: HashMap<_, _> = req
+            .history
+            .iter()
+            .map(|h| (h.manga_id, h.version()))
+            .collect();
+        data.history
+            .retain(|h| history.get(&h.manga_id) != Some(&h.version()));
+        return Ok(with_conflict(Json(data), conflict));
+    }
 
     match req.0 == data {
         // is this real usecase?
         true => Ok(ResponseData::Status(Status::NoContent)),
-        false => Ok(Json(data).into()),
+        false => Ok(with_conflict(Json(data), conflict)),
     }
 }
 
-#[get("/history")]
+/// Get history. `since` works like for favourites
+#[get("/history?<since>")]
 pub fn get_history(
+    since: Option<Time>,
     token: Result<ApiToken, AuthError>,
     db: &State<DB>,
 ) -> Response<Json<common::HistoryPackage>> {
     let user = user_by_token(token, db)?;
-    let data = db.load_history_package(user.id).map_err(|e| {
+    let data = db.load_history_package(user.id, since).map_err(|e| {
         log::error!("failed to load history_package for user {}: {e}", user.id);
         ResponseData::Status(Status::InternalServerError)
     })?;
     Ok(Json(data).into())
 }
+
+/// Import Kotatsu app backup archive. Items are merged like in sync, report
+/// contains counts of parsed and skipped entries
+#[post("/backup", data = "<req>")]
+pub fn import_backup(
+    req: Vec<u8>,
+    token: Result<ApiToken, AuthError>,
+    config: &State<Conf>,
+    db: &State<DB>,
+) -> Response<Json<response::BackupImport>> {
+    let user = user_by_token(token, db)?;
+
+    let backup = backup::read(&req, config.server.limits.backup.as_u64()).map_err(|e| {
+        log::debug!("failed to read backup: {e}");
+        ResponseData::from((Status::BadRequest, format!("invalid backup: {e}")))
+    })?;
+
+    db.add_favourites_package(&backup.favourites, user.id, false)
+        .map_err(|e| {
+            log::error!("failed to import favourites: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?;
+    db.add_history_package(&backup.history, user.id, false)
+        .map_err(|e| {
+            log::error!("failed to import history: {e}");
+            ResponseData::Status(Status::InternalServerError)
+        })?;
+
+    Ok(Json(backup.report).into())
+}
+
+/// Adds header, which tells client that its data was merged with changes of
+/// other client
+fn with_conflict<T>(data: Json<T>, conflict: bool) -> ResponseData<Json<T>> {
+    match conflict {
+        true => ResponseData::StatusMessageHeader(
+            Custom(Status::Ok, data),
+            Header::new(SYNC_CONFLICT_HEADER, "true"),
+        ),
+        false => data.into(),
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use rocket::{
+    fairing::AdHoc,
+    tokio::{self, task::JoinHandle},
+};
+
+use crate::{
+    config::Conf,
+    current_timestamp,
+    db::conn::DB,
+    gc,
+    models::{admin::JobStatus, common::Time},
+    snapshot,
+};
+
+type JobFn = Box<dyn Fn(&DB) -> Result<()> + Send + Sync>;
+
+/// Runs maintenance jobs periodically in background, while server is running.
+/// Jobs are run one by one, so they don't compete for database
+pub struct Scheduler {
+    jobs: Vec<Arc<Job>>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+struct Job {
+    run: JobFn,
+    status: Mutex<JobStatus>,
+}
+
+impl Scheduler {
+    pub fn new(config: &Conf) -> Self {
+        let interval = |secs: u64| (secs > 0).then_some(secs);
+
+        let gc_conf = config.gc.clone();
+        let session_lifetime = config.jwt.access_lifetime as i64 * 1000;
+        let mut jobs = vec![
+            Job::new(
+                "gc",
+                interval(config.gc.interval).filter(|_| config.gc.enabled),
+                move |db| gc::run(db, &gc_conf).map(|_| ()),
+            ),
+            Job::new(
+                "prune_sessions",
+                interval(config.jobs.prune_sessions_interval),
+                move |db| {
+                    let now = current_timestamp().unwrap_or_default();
+                    let count = db.prune_sessions(now - session_lifetime)?;
+                    log::info!("pruned {count} sessions");
+                    Ok(())
+                },
+            ),
+            Job::new(
+                "optimize_db",
+                interval(config.jobs.optimize_db_interval),
+                |db| db.optimize(),
+            ),
+        ];
+        if snapshot::enabled(&config.backup) {
+            let backup_conf = config.backup.clone();
+            // so restarts don't replace old backups with new ones
+            let last_backup_at = snapshot::last(&config.backup)
+                .inspect_err(|e| log::error!("failed to list backups: {e}"))
+                .ok()
+                .flatten();
+            jobs.push(
+                Job::new("backup", interval(config.backup.interval), move |db| {
+                    snapshot::run(db, &backup_conf).map(|_| ())
+                })
+                .with_last_finished_at(last_backup_at),
+            );
+        }
+
+        Self {
+            jobs: jobs.into_iter().map(Arc::new).collect(),
+            handle: Mutex::default(),
+        }
+    }
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.jobs.iter().map(|j| j.status()).collect()
+    }
+    /// Run job now. Returns `None` if there is no job with such name
+    pub fn run(&self, name: &str, db: &DB) -> Option<Result<()>> {
+        let job = self.jobs.iter().find(|j| j.status().name == name)?;
+        Some(job.run(db))
+    }
+    /// Starts jobs, which have interval, on liftoff
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_liftoff("scheduler", |rocket| {
+            Box::pin(async move {
+                let (Some(scheduler), Some(db)) =
+                    (rocket.state::<Scheduler>(), rocket.state::<DB>())
+                else {
+                    log::error!("failed to get scheduler from state, jobs are disabled");
+                    return;
+                };
+
+                let jobs: Vec<_> = scheduler
+                    .jobs
+                    .iter()
+                    .filter_map(|j| Some((j.clone(), Duration::from_secs(j.status().interval?))))
+                    .collect();
+                if jobs.is_empty() {
+                    return;
+                }
+
+                let handle = tokio::spawn(schedule(jobs, db.clone(), rocket.shutdown()));
+                *scheduler.handle.lock().unwrap_or_else(|e| e.into_inner()) = Some(handle);
+            })
+        })
+    }
+    /// Stops jobs on shutdown, waiting for running ones to finish
+    pub fn shutdown_fairing() -> AdHoc {
+        AdHoc::on_shutdown("scheduler shutdown", |rocket| {
+            Box::pin(async move {
+                let Some(scheduler) = rocket.state::<Scheduler>() else {
+                    return;
+                };
+                let handle = scheduler
+                    .handle
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .take();
+                if let Some(handle) = handle
+                    && let Err(e) = handle.await
+                {
+                    log::error!("failed to stop scheduler: {e}");
+                }
+            })
+        })
+    }
+}
+
+impl Job {
+    fn new(
+        name: &str,
+        interval: Option<u64>,
+        run: impl Fn(&DB) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            run: Box::new(run),
+            status: Mutex::new(JobStatus {
+                name: name.to_string(),
+                interval,
+                running: false,
+                runs: 0,
+                failures: 0,
+                last_started_at: None,
+                last_finished_at: None,
+                last_error: None,
+            }),
+        }
+    }
+    /// Set time of the last run, made before start
+    fn with_last_finished_at(self, time: Option<Time>) -> Self {
+        self.status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .last_finished_at = time;
+        self
+    }
+    /// Time until the first run. It's one interval after the last run, or
+    /// after start, so restarts don't run all jobs at once
+    fn first_run_delay(&self, interval: Duration) -> Duration {
+        let Some(last) = self.status().last_finished_at else {
+            return interval;
+        };
+        let now = current_timestamp().unwrap_or_default();
+        let since_last = Duration::from_millis(now.saturating_sub(last).max(0) as u64);
+        interval.saturating_sub(since_last)
+    }
+    fn status(&self) -> JobStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+    fn run(&self, db: &DB) -> Result<()> {
+        let name = {
+            let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+            if status.running {
+                return Err(anyhow!("job {} is already running", status.name));
+            }
+            status.running = true;
+            status.last_started_at = current_timestamp();
+            status.name.clone()
+        };
+
+        log::info!("starting job {name}");
+        let started = Instant::now();
+        let res = (self.run)(db);
+
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        status.running = false;
+        status.runs += 1;
+        status.last_finished_at = current_timestamp();
+        status.last_error = res.as_ref().err().map(|e| e.to_string());
+        match &res {
+            Ok(()) => log::info!("job {name} finished in {:?}", started.elapsed()),
+            Err(e) => {
+                status.failures += 1;
+                log::error!("job {name} failed: {e}");
+            }
+        }
+        res
+    }
+}
+
+/// Run jobs with their intervals until shutdown, starting one interval after
+/// start. Running job is not interrupted
+async fn schedule(jobs: Vec<(Arc<Job>, Duration)>, db: DB, shutdown: rocket::Shutdown) {
+    let start = Instant::now();
+    let mut next_runs: Vec<_> = jobs
+        .iter()
+        .map(|(job, interval)| start + job.first_run_delay(*interval))
+        .collect();
+    loop {
+        let Some((i, next_run)) = next_runs
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by_key(|(_, t)| *t)
+        else {
+            return;
+        };
+        tokio::select! {
+            _ = shutdown.clone() => return,
+            _ = tokio::time::sleep_until(next_run.into()) => (),
+        }
+
+        let (job, interval) = &jobs[i];
+        let job = job.clone();
+        let db = db.clone();
+        // error is already logged
+        if let Err(e) = tokio::task::spawn_blocking(move || job.run(&db)).await {
+            log::error!("failed to join job: {e}");
+        }
+        next_runs[i] = Instant::now() + *interval;
+    }
+}
@@ -0,0 +1,108 @@
+//! Copies of SQLite database, made while server is running, with rotation
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::{
+    config::ConfBackup,
+    current_timestamp,
+    db::conn::DB,
+    models::{admin, common::Time},
+};
+
+const PREFIX: &str = "kotync-";
+const EXTENSION: &str = "db";
+
+/// Whether backups are configured and supported by database
+pub fn enabled(conf: &ConfBackup) -> bool {
+    cfg!(feature = "sqlite") && conf.dir.is_some()
+}
+
+/// Make new backup and remove old ones, so only `keep` newest are left
+pub fn run(db: &DB, conf: &ConfBackup) -> Result<admin::Backup> {
+    let dir = conf
+        .dir
+        .as_deref()
+        .filter(|_| enabled(conf))
+        .ok_or_else(|| anyhow!("backups are disabled"))?;
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let created_at = current_timestamp().context("failed to get current time")?;
+    let path = dir.join(format!("{PREFIX}{created_at}.{EXTENSION}"));
+    if path.exists() {
+        return Err(anyhow!("backup {} already exists", path.display()));
+    }
+
+    // backup is written to temporary file, so incomplete one is never listed
+    let tmp = path.with_extension("tmp");
+    remove_if_exists(&tmp)?;
+    db.backup_into(tmp.to_str().context("backup path is not valid UTF-8")?)?;
+    fs::rename(&tmp, &path)?;
+    let size = fs::metadata(&path)?.len();
+    log::info!("saved backup {} ({size} bytes)", path.display());
+
+    if conf.keep > 0 {
+        let backups = list(dir)?;
+        let outdated = backups.len().saturating_sub(conf.keep);
+        for (_, old) in &backups[..outdated] {
+            fs::remove_file(old).with_context(|| format!("failed to remove {}", old.display()))?;
+            log::info!("removed old backup {}", old.display());
+        }
+    }
+
+    Ok(admin::Backup {
+        path: path.display().to_string(),
+        created_at,
+        size,
+    })
+}
+
+/// Time of the newest backup
+pub fn last(conf: &ConfBackup) -> Result<Option<Time>> {
+    let Some(dir) = conf.dir.as_deref().filter(|_| enabled(conf)) else {
+        return Ok(None);
+    };
+    let dir = Path::new(dir);
+    // created on first backup
+    if !dir.exists() {
+        return Ok(None);
+    }
+    Ok(list(dir)?.last().map(|(time, _)| *time))
+}
+
+/// Backups in directory, from oldest to newest
+fn list(dir: &Path) -> Result<Vec<(Time, PathBuf)>> {
+    let mut backups = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if let Some(time) = parse_time(&path) {
+            backups.push((time, path));
+        }
+    }
+    backups.sort();
+    Ok(backups)
+}
+
+fn parse_time(path: &Path) -> Option<Time> {
+    if path.extension()? != EXTENSION {
+        return None;
+    }
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix(PREFIX)?
+        .parse()
+        .ok()
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
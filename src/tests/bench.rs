@@ -0,0 +1,140 @@
+//! Benchmarks, run with `cargo test --release bench -- --ignored --nocapture`
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use diesel::{Connection, SqliteConnection, prelude::*};
+
+use crate::{
+    current_timestamp,
+    db::schema::{favourites, manga, manga_tags, tags},
+    models::{
+        common::{self, Time, UserID},
+        db::{Favourite, Manga, Tag},
+    },
+    tests::e2e::utils::get_db,
+};
+
+const MANGA_COUNT: i64 = 2000;
+const TAGS_PER_MANGA: i64 = 5;
+
+#[test]
+#[ignore]
+fn bench_load_favourites() -> Result<()> {
+    let (db_conf, db) = get_db()?;
+    let user = db.create_user("bench@example.com", "")?;
+    let now = current_timestamp().unwrap();
+    db.add_favourites_package(&favourites_package(now), user.id, false)?;
+
+    let (batched, package) = measure(|| db.load_favourites_package(user.id, None))?;
+    assert_eq!(package.favourites.len(), MANGA_COUNT as usize);
+    assert!(
+        package
+            .favourites
+            .iter()
+            .all(|f| f.manga.tags.len() == TAGS_PER_MANGA as usize)
+    );
+
+    let conn = &mut SqliteConnection::establish(&db_conf.url)?;
+    let (per_manga, favourites) = measure(|| load_favourites_per_manga(conn, user.id))?;
+    assert_eq!(favourites.len(), MANGA_COUNT as usize);
+    assert!(
+        favourites
+            .iter()
+            .all(|(_, _, tags)| tags.len() == TAGS_PER_MANGA as usize)
+    );
+
+    println!("load {MANGA_COUNT} favourites: {batched:?}, query per manga: {per_manga:?}");
+
+    Ok(())
+}
+
+/// Load favourites like it was done before tags were batched: with two
+/// queries for tags of each manga
+fn load_favourites_per_manga(
+    conn: &mut SqliteConnection,
+    user_id: UserID,
+) -> Result<Vec<(Favourite, Manga, Vec<Tag>)>> {
+    let rows: Vec<(Favourite, Manga)> = favourites::table
+        .inner_join(manga::table)
+        .filter(favourites::user_id.eq(user_id))
+        .select((Favourite::as_select(), Manga::as_select()))
+        .load(conn)?;
+    rows.into_iter()
+        .map(|(fav, manga)| {
+            let tag_ids: Vec<i64> = manga_tags::table
+                .filter(manga_tags::manga_id.eq(manga.id))
+                .select(manga_tags::tag_id)
+                .load(conn)?;
+            let tags = tags::table
+                .filter(tags::id.eq_any(tag_ids))
+                .select(Tag::as_select())
+                .load(conn)?;
+            Ok((fav, manga, tags))
+        })
+        .collect()
+}
+
+fn measure<T>(mut f: impl FnMut() -> Result<T>) -> Result<(Duration, T)> {
+    // warming up connection pool and caches
+    f()?;
+    let started = Instant::now();
+    let res = f()?;
+    Ok((started.elapsed(), res))
+}
+
+fn favourites_package(now: Time) -> common::FavouritesPackage {
+    common::FavouritesPackage {
+        categories: vec![common::Category {
+            id: 1,
+            created_at: now,
+            sort_key: 0,
+            track: false,
+            title: "bench".to_string(),
+            order: "NEWEST".to_string(),
+            deleted_at: 0,
+            show_in_lib: true,
+        }],
+        favourites: (1..=MANGA_COUNT)
+            .map(|id| common::Favourite {
+                manga_id: id,
+                manga: manga(id),
+                category_id: 1,
+                sort_key: id as i32,
+                pinned: false,
+                created_at: now,
+                deleted_at: 0,
+            })
+            .collect(),
+        timestamp: None,
+    }
+}
+
+fn manga(id: i64) -> common::Manga {
+    common::Manga {
+        id,
+        title: format!("manga {id}"),
+        alt_title: None,
+        url: format!("kotatsu://manga/{id}"),
+        public_url: format!("http://example.com/manga/{id}"),
+        rating: 0.5,
+        content_rating: None,
+        cover_url: format!("http://example.com/cover/{id}"),
+        large_cover_url: None,
+        // tags are shared between manga, like in real library
+        tags: (0..TAGS_PER_MANGA)
+            .map(|i| {
+                let tag = (id + i) % 100;
+                common::MangaTag {
+                    id: tag,
+                    title: format!("tag {tag}"),
+                    key: format!("tag{tag}"),
+                    source: "source".to_string(),
+                }
+            })
+            .collect(),
+        state: None,
+        author: None,
+        source: "source".to_string(),
+    }
+}
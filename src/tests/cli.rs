@@ -0,0 +1,70 @@
+use anyhow::Result;
+use clap::CommandFactory;
+use rocket::{http::Status, uri};
+
+use crate::{
+    cli::{self, Cli},
+    models::{request, response},
+    routes,
+    tests::e2e::utils::{get_db, prepare_client_with_conf_and_db},
+};
+
+#[test]
+fn test_cli_definition() {
+    Cli::command().debug_assert();
+}
+
+#[test]
+fn test_cli_users() -> Result<()> {
+    let (db_conf, db) = get_db()?;
+    let client = prepare_client_with_conf_and_db(false, db_conf, db.clone())?;
+    let email = "cli@example.com";
+
+    assert!(cli::add_user(&db, "invalid", "test").is_err());
+    assert!(cli::add_user(&db, email, "").is_err());
+    cli::add_user(&db, email, "test")?;
+    assert!(cli::add_user(&db, email, "test").is_err());
+
+    // user can log in, even though registration is disabled
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let auth: response::Auth = resp.into_json().unwrap();
+
+    assert!(cli::set_password(&db, "unknown@example.com", "new").is_err());
+    cli::set_password(&db, email, "new")?;
+
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, "new"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    // sessions with old password are revoked
+    let resp = client
+        .post(uri!(routes::base::refresh))
+        .json(&request::Refresh::new(&auth.refresh_token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_cli_migrate() -> Result<()> {
+    let (_, db) = get_db()?;
+
+    assert!(db.migrate()?.is_empty());
+    let reverted = db.revert_migration()?;
+    assert_eq!(db.migrate()?, [reverted]);
+
+    Ok(())
+}
@@ -2,13 +2,25 @@
 
 use anyhow::Result;
 use rocket::{
-    http::{Header, Status, hyper::header::AUTHORIZATION, uri::Origin},
+    http::{
+        ContentType, Header, Status,
+        hyper::header::{AUTHORIZATION, USER_AGENT},
+        uri::Origin,
+    },
+    local::blocking::Client,
     uri,
 };
 
 use crate::{
     current_timestamp,
-    models::{admin::DBStats, common, request, response},
+    db::conn::DB,
+    models::{
+        admin,
+        admin::Stats,
+        common,
+        common::{SessionID, Time, UserID},
+        request, response,
+    },
     routes,
 };
 
@@ -108,258 +120,2371 @@ fn test_auth_disabled() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_separate_register() -> Result<()> {
+    let client = prepare_client_with(|c| c.server.separate_register = true)?;
+    let email = "test@example.com";
+
+    let auth = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, "test"));
+    // unknown email is not revealed
+    let resp = auth.clone().dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "Wrong password");
+
+    let register = client
+        .post(uri!(routes::base::register))
+        .json(&request::Auth::new(email, "test"));
+    let resp = register.clone().dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let resp: response::Auth = resp.into_json().unwrap();
+
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(bearer(&resp.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = auth.dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = register.dispatch();
+    assert_eq!(resp.status(), Status::Conflict);
+    assert_eq!(resp.into_string().unwrap(), "User already exists");
+
+    Ok(())
+}
+
+#[test]
+fn test_register_disabled() -> Result<()> {
+    let client = prepare_client_with_conf(false)?;
+
+    let resp = client
+        .post(uri!(routes::base::register))
+        .json(&request::Auth::new("test@example.com", "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    Ok(())
+}
+
+#[test]
+fn test_auth_invite() -> Result<()> {
+    let client = prepare_client_with_conf(false)?;
+
+    let resp = client
+        .post(uri!(ADMIN.clone(), routes::admin::create_invite))
+        .header(admin_auth())
+        .json(&admin::CreateInvite {
+            max_uses: 1,
+            expires_in: Some(60),
+        })
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let invite: admin::Invite = resp.into_json().unwrap();
+    assert_eq!(invite.uses, 0);
+
+    let auth = |email: &str, invite: &str| {
+        let mut req = request::Auth::new(email, "test");
+        req.invite = Some(invite.to_string());
+        client.post(uri!(routes::base::auth)).json(&req).dispatch()
+    };
+
+    let resp = auth("test@example.com", &invite.code);
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = auth("test2@example.com", &invite.code);
+    assert_eq!(resp.status(), Status::Forbidden);
+    assert_eq!(resp.into_string().unwrap(), "Invalid invite code");
+
+    let resp = auth("test2@example.com", "unknown");
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    // expired
+    let resp = client
+        .post(uri!(ADMIN.clone(), routes::admin::create_invite))
+        .header(admin_auth())
+        .json(&admin::CreateInvite {
+            max_uses: 1,
+            expires_in: Some(0),
+        })
+        .dispatch();
+    let expired: admin::Invite = resp.into_json().unwrap();
+    let resp = auth("test2@example.com", &expired.code);
+    assert_eq!(resp.status(), Status::Forbidden);
+
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::list_invites))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let invites: Vec<admin::Invite> = resp.into_json().unwrap();
+    assert_eq!(invites.len(), 2);
+    assert_eq!(invites[0].code, invite.code);
+    assert_eq!(invites[0].uses, 1);
+    assert_eq!(invites[1].uses, 0);
+
+    Ok(())
+}
+
 #[test]
 fn test_auth_invalid_password() -> Result<()> {
     let client = prepare_client()?;
     let email = "test@example.com".to_string();
 
     let resp = client
-        .post(uri!(routes::base::auth))
-        .json(&request::Auth::new(&email, "test"))
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(&email, "test"))
+        .dispatch();
+
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(&email, "asdf"))
+        .dispatch();
+
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "Wrong password");
+
+    Ok(())
+}
+
+#[test]
+fn test_auth_rehash_blake3() -> Result<()> {
+    let email = "test@example.com";
+    let password = "test";
+    let (db_conf, db) = get_db()?;
+    db.create_user(email, &blake3::hash(password.as_bytes()).to_string())?;
+
+    let client = prepare_client_with_conf_and_db(true, db_conf, db)?;
+
+    let req = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, password));
+    let resp = req.clone().dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let db = client.rocket().state::<DB>().unwrap();
+    let user = db.get_user_by_email(email)?.unwrap();
+    assert!(user.password_hash.starts_with("$argon2id$"));
+
+    let resp = req.dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, "asdf"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    Ok(())
+}
+
+#[test]
+fn test_auth_refresh() -> Result<()> {
+    let client = prepare_client()?;
+
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new("test@example.com", "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let auth: response::Auth = resp.into_json().unwrap();
+
+    let req = client
+        .post(uri!(routes::base::refresh))
+        .json(&request::Refresh::new(&auth.refresh_token));
+    let resp = req.clone().dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let refreshed: response::Auth = resp.into_json().unwrap();
+    assert_ne!(refreshed.refresh_token, auth.refresh_token);
+
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(bearer(&refreshed.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    // reuse of old refresh token revokes the whole session
+    let resp = req.dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+    assert_eq!(resp.into_string().unwrap(), "invalid refresh token");
+
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(bearer(&refreshed.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+    assert_eq!(resp.into_string().unwrap(), "session revoked");
+
+    let resp = client
+        .post(uri!(routes::base::refresh))
+        .json(&request::Refresh::new(&refreshed.refresh_token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    Ok(())
+}
+
+#[test]
+fn test_auth_logout() -> Result<()> {
+    let client = prepare_client()?;
+
+    let auth = |client: &Client| -> response::Auth {
+        let resp = client
+            .post(uri!(routes::base::auth))
+            .json(&request::Auth::new("test@example.com", "test"))
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        resp.into_json().unwrap()
+    };
+    let first = auth(&client);
+    let second = auth(&client);
+
+    let req = client
+        .post(uri!(routes::base::logout))
+        .json(&request::Refresh::new(&first.refresh_token));
+    let resp = req.clone().dispatch();
+    assert_eq!(resp.status(), Status::NoContent);
+
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(bearer(&first.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let resp = client
+        .post(uri!(routes::base::refresh))
+        .json(&request::Refresh::new(&first.refresh_token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    // other sessions are not affected
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(bearer(&second.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .post(uri!(routes::base::logout))
+        .json(&request::Refresh::new("unknown"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    Ok(())
+}
+
+#[test]
+fn test_sessions() -> Result<()> {
+    let client = prepare_client()?;
+
+    let auth = |device_name: &str, user_agent: &str| -> response::Auth {
+        let mut req = request::Auth::new("test@example.com", "test");
+        req.device_name = Some(device_name.to_string());
+        let resp = client
+            .post(uri!(routes::base::auth))
+            .header(Header::new(USER_AGENT.as_str(), user_agent.to_string()))
+            .json(&req)
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        resp.into_json().unwrap()
+    };
+    let phone = auth("phone", "Kotatsu/9.2");
+    let tablet = auth("tablet", "Kotatsu/9.1");
+
+    let resp = client
+        .get(uri!(routes::base::list_sessions))
+        .header(bearer(&phone.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let mut sessions: Vec<response::Session> = resp.into_json().unwrap();
+    sessions.sort_by_key(|s| s.id);
+
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0].device_name.as_deref(), Some("phone"));
+    assert_eq!(sessions[0].user_agent.as_deref(), Some("Kotatsu/9.2"));
+    assert!(sessions[0].current);
+    assert_eq!(sessions[1].device_name.as_deref(), Some("tablet"));
+    assert!(!sessions[1].current);
+
+    let tablet_id = sessions[1].id;
+    let resp = client
+        .delete(uri!(routes::base::delete_session(tablet_id)))
+        .header(bearer(&phone.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::NoContent);
+
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(bearer(&tablet.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let resp = client
+        .get(uri!(routes::base::list_sessions))
+        .header(bearer(&phone.token))
+        .dispatch();
+    let sessions: Vec<response::Session> = resp.into_json().unwrap();
+    assert_eq!(sessions.len(), 1);
+
+    let resp = client
+        .delete(uri!(routes::base::delete_session(tablet_id)))
+        .header(bearer(&phone.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::NotFound);
+
+    // session of other user
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new("test2@example.com", "test"))
+        .dispatch();
+    let other: response::Auth = resp.into_json().unwrap();
+    let resp = client
+        .delete(uri!(routes::base::delete_session(sessions[0].id)))
+        .header(bearer(&other.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::NotFound);
+
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_sessions() -> Result<()> {
+    use crate::models::db::SessionInsert;
+
+    let (_, db) = get_db()?;
+    let user = db.create_user("test@example.com", "hash")?;
+
+    // logins of the same user at the same time
+    let created = std::thread::scope(|s| {
+        let logins: Vec<_> = (0..8)
+            .map(|i| {
+                let db = &db;
+                s.spawn(move || -> Result<(SessionID, String)> {
+                    let token = format!("token{i}");
+                    let session = SessionInsert::new(user.id, Some(format!("device {i}")), None);
+                    Ok((db.create_session(session, &token, Time::MAX)?, token))
+                })
+            })
+            .collect();
+        logins
+            .into_iter()
+            .map(|l| l.join().unwrap())
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let mut ids: Vec<_> = created.iter().map(|(id, _)| *id).collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), created.len());
+
+    // each refresh token belongs to its own session
+    for (id, token) in created {
+        let session = db.rotate_refresh_token(&token, &format!("{token}-new"), Time::MAX)?;
+        assert_eq!(session.map(|s| s.id), Some(id));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_auth_rate_limit_email() -> Result<()> {
+    let client = prepare_client_with(|c| c.server.rate_limit.max_per_email = 3)?;
+    let email = "test@example.com";
+    make_user(&client);
+
+    let wrong = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, "asdf"));
+    for _ in 0..3 {
+        let resp = wrong.clone().dispatch();
+        assert_eq!(resp.status(), Status::BadRequest);
+    }
+
+    // correct password is also rejected during lockout
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::TooManyRequests);
+    assert_eq!(resp.headers().get_one("Retry-After"), Some("60"));
+
+    // other emails are not affected
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new("test2@example.com", "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::stats))
+        .header(admin_auth())
+        .dispatch();
+    let resp: Stats = resp.into_json().unwrap();
+    assert_eq!(resp.rate_limit.failed_attempts, 3);
+    assert_eq!(resp.rate_limit.blocked_requests, 1);
+    assert_eq!(resp.rate_limit.locked_keys, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_auth_rate_limit_ip() -> Result<()> {
+    let client = prepare_client_with(|c| {
+        c.server.rate_limit.max_per_ip = 2;
+        c.server.ip_header = Some("X-Real-IP".to_string());
+    })?;
+    let ip = Header::new("X-Real-IP", "10.0.0.1");
+    make_user(&client);
+
+    for _ in 0..2 {
+        let resp = client
+            .post(uri!(routes::base::auth))
+            .header(ip.clone())
+            .json(&request::Auth::new("test@example.com", "asdf"))
+            .dispatch();
+        assert_eq!(resp.status(), Status::BadRequest);
+    }
+
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .header(ip)
+        .json(&request::Auth::new("test2@example.com", "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::TooManyRequests);
+
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .header(Header::new("X-Real-IP", "10.0.0.2"))
+        .json(&request::Auth::new("test2@example.com", "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    Ok(())
+}
+
+/// IP header can be set by any client, when server is not behind proxy
+#[test]
+fn test_auth_rate_limit_ip_header_untrusted() -> Result<()> {
+    let client = prepare_client_with(|c| c.server.rate_limit.max_per_ip = 2)?;
+    make_user(&client);
+    let remote: std::net::SocketAddr = "10.0.1.1:50000".parse()?;
+
+    for i in 0..2 {
+        let resp = client
+            .post(uri!(routes::base::auth))
+            .remote(remote)
+            .header(Header::new("X-Real-IP", format!("10.0.0.{i}")))
+            .json(&request::Auth::new("test@example.com", "asdf"))
+            .dispatch();
+        assert_eq!(resp.status(), Status::BadRequest);
+    }
+
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .remote(remote)
+        .header(Header::new("X-Real-IP", "10.0.0.2"))
+        .json(&request::Auth::new("test2@example.com", "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::TooManyRequests);
+
+    Ok(())
+}
+
+#[test]
+fn test_change_password() -> Result<()> {
+    let client = prepare_client()?;
+    let email = "test@example.com";
+
+    let auth = |password: &str| {
+        client
+            .post(uri!(routes::base::auth))
+            .json(&request::Auth::new(email, password))
+            .dispatch()
+    };
+    let current: response::Auth = auth("test").into_json().unwrap();
+    let other: response::Auth = auth("test").into_json().unwrap();
+
+    let change = |current_password: &str, new_password: &str| {
+        client
+            .post(uri!(routes::base::change_password))
+            .header(bearer(&current.token))
+            .json(&request::ChangePassword::new(
+                current_password,
+                new_password,
+            ))
+            .dispatch()
+    };
+
+    // token is checked before request body
+    let resp = client
+        .post(uri!(routes::base::change_password))
+        .json(&request::ChangePassword::new("test", "n"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let resp = change("asdf", "new");
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "Wrong password");
+
+    let resp = change("test", "n");
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    let resp = change("test", "new");
+    assert_eq!(resp.status(), Status::NoContent);
+
+    // other sessions are revoked
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(bearer(&other.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(bearer(&current.token))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    assert_eq!(auth("test").status(), Status::BadRequest);
+    assert_eq!(auth("new").status(), Status::Ok);
+
+    Ok(())
+}
+
+#[test]
+fn test_change_password_rate_limit() -> Result<()> {
+    let client = prepare_client_with(|c| c.server.rate_limit.max_per_email = 3)?;
+    let auth = make_user(&client);
+
+    let wrong = client
+        .post(uri!(routes::base::change_password))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .json(&request::ChangePassword::new("asdf", "new"));
+    for _ in 0..3 {
+        let resp = wrong.clone().dispatch();
+        assert_eq!(resp.status(), Status::BadRequest);
+    }
+
+    // correct password is also rejected during lockout
+    let resp = client
+        .post(uri!(routes::base::change_password))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .json(&request::ChangePassword::new("test", "new"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::TooManyRequests);
+    assert_eq!(resp.headers().get_one("Retry-After"), Some("60"));
+
+    // the same limit as for login
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new("test@example.com", "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::TooManyRequests);
+
+    Ok(())
+}
+
+#[test]
+fn test_password_reset() -> Result<()> {
+    let client = prepare_client()?;
+    let email = "test@example.com";
+    let auth = make_user(&client);
+
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    let me: response::Me = resp.into_json().unwrap();
+
+    let resp = client
+        .post(uri!(
+            ADMIN.clone(),
+            routes::admin::create_password_reset(me.id, Some(60))
+        ))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let reset: admin::PasswordReset = resp.into_json().unwrap();
+
+    let req = client
+        .post(uri!(routes::base::reset_password))
+        .json(&request::ResetPassword::new(&reset.token, "new"));
+    let resp = req.clone().dispatch();
+    assert_eq!(resp.status(), Status::NoContent);
+
+    // token is single-use
+    let resp = req.dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "Invalid reset token");
+
+    // sessions are revoked
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, "new"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    // expired token
+    let resp = client
+        .post(uri!(
+            ADMIN.clone(),
+            routes::admin::create_password_reset(me.id, Some(0))
+        ))
+        .header(admin_auth())
+        .dispatch();
+    let reset: admin::PasswordReset = resp.into_json().unwrap();
+    let resp = client
+        .post(uri!(routes::base::reset_password))
+        .json(&request::ResetPassword::new(&reset.token, "new2"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    let resp = client
+        .post(uri!(
+            ADMIN.clone(),
+            routes::admin::create_password_reset(me.id + 1, None::<u64>)
+        ))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::NotFound);
+
+    Ok(())
+}
+
+#[test]
+fn test_delete_me() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&data::favourites_package())
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client
+        .post(uri!(RESOURCE.clone(), routes::resource::save_history(_, _)))
+        .json(&data::history_package())
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .delete(uri!(routes::base::delete_me))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::NoContent);
+
+    let resp = client
+        .get(uri!(routes::base::me))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::stats))
+        .header(admin_auth())
+        .dispatch();
+    let resp: Stats = resp.into_json().unwrap();
+    assert_eq!(resp.db.users_count, 0);
+
+    // new user with the same email has no data
+    let auth = make_user(&client);
+    let resp = client
+        .get(uri!(RESOURCE.clone(), routes::resource::get_favourites(_)))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    let resp: common::FavouritesPackage = resp.into_json().unwrap();
+    assert!(resp.categories.is_empty());
+    assert!(resp.favourites.is_empty());
+
+    let resp = client
+        .get(uri!(RESOURCE.clone(), routes::resource::get_history(_)))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    let resp: common::HistoryPackage = resp.into_json().unwrap();
+    assert!(resp.history.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_favourites() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let data = data::favourites_package();
+
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&data)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .get(uri!(RESOURCE.clone(), routes::resource::get_favourites(_)))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+
+    assert_eq!(resp.status(), Status::Ok);
+
+    let mut data = data;
+    let sent_timestamp = data.timestamp.take();
+
+    let mut resp: common::FavouritesPackage = resp.into_json().unwrap();
+    let timestamp = resp.timestamp.take();
+
+    similar_asserts::assert_eq!(data, resp);
+    assert!(timestamp < current_timestamp());
+    if let Some(sent_timestamp) = sent_timestamp {
+        assert!(timestamp.is_some_and(|t| t > sent_timestamp));
+    } else {
+        assert!(timestamp.is_some());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_favourites_tags() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    // the same manga in two categories and other manga with own tags
+    let mut data = data::favourites_package();
+    data.categories.push(data::category(2));
+    let mut favourite = data::favourite(1);
+    favourite.category_id = 2;
+    data.favourites.push(favourite);
+
+    let mut favourite = data::favourite(2);
+    favourite.manga.tags = vec![common::MangaTag {
+        id: 3,
+        title: "Test 3".to_string(),
+        key: "test3".to_string(),
+        source: "source".to_string(),
+    }];
+    data.favourites.push(favourite);
+
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&data)
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let mut resp: common::FavouritesPackage = resp.into_json().unwrap();
+    let key = |f: &common::Favourite| (f.category_id, f.manga_id);
+    resp.favourites.sort_by_key(key);
+    data.favourites.sort_by_key(key);
+    resp.timestamp = None;
+    data.timestamp = None;
+    similar_asserts::assert_eq!(data, resp);
+
+    Ok(())
+}
+
+#[test]
+// todo: test for sync which doesn't change data in db (which returns 204)
+fn test_sync_history() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let data = data::history_package();
+
+    let resp = client
+        .post(uri!(RESOURCE.clone(), routes::resource::save_history(_, _)))
+        .json(&data)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .get(uri!(RESOURCE.clone(), routes::resource::get_history(_)))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+
+    assert_eq!(resp.status(), Status::Ok);
+
+    let mut data = data;
+    let sent_timestamp = data.timestamp.take();
+
+    let mut resp: common::HistoryPackage = resp.into_json().unwrap();
+    let timestamp = resp.timestamp.take();
+
+    similar_asserts::assert_eq!(data, resp);
+    assert!(timestamp < current_timestamp());
+    if let Some(sent_timestamp) = sent_timestamp {
+        assert!(timestamp.is_some_and(|t| t > sent_timestamp));
+    } else {
+        assert!(timestamp.is_some());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_delta_sync() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let sync = |pkg: &common::HistoryPackage| -> common::HistoryPackage {
+        let resp = client
+            .post(uri!(
+                RESOURCE.clone(),
+                routes::resource::save_history(Some(true), _)
+            ))
+            .json(pkg)
+            .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        resp.into_json().unwrap()
+    };
+
+    // first device sends everything
+    let mut first = data::history_package();
+    first.timestamp = None;
+    let resp = sync(&first);
+    // sent items are not returned back
+    assert!(resp.history.is_empty());
+    let first_timestamp = resp.timestamp.unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+
+    // second device sends only new item
+    let mut second = data::history_package();
+    second.history[0].manga_id = 2;
+    second.history[0].manga.id = 2;
+    second.timestamp = None;
+    let resp = sync(&second);
+    // and receives item of the first device
+    assert_eq!(resp.history.len(), 1);
+    assert_eq!(resp.history[0].manga_id, 1);
+
+    // first device receives only changes since its last sync
+    let resp = sync(&common::HistoryPackage {
+        history: vec![],
+        timestamp: Some(first_timestamp),
+    });
+    assert_eq!(resp.history.len(), 1);
+    assert_eq!(resp.history[0].manga_id, 2);
+    let first_timestamp = resp.timestamp.unwrap();
+
+    let resp = sync(&common::HistoryPackage {
+        history: vec![],
+        timestamp: Some(first_timestamp),
+    });
+    assert!(resp.history.is_empty());
+
+    let resp = client
+        .get(uri!(
+            RESOURCE.clone(),
+            routes::resource::get_history(Some(first_timestamp))
+        ))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    let resp: common::HistoryPackage = resp.into_json().unwrap();
+    assert!(resp.history.is_empty());
+    assert!(resp.timestamp.is_some_and(|t| t >= first_timestamp));
+
+    let resp = client
+        .get(uri!(RESOURCE.clone(), routes::resource::get_history(_)))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    let resp: common::HistoryPackage = resp.into_json().unwrap();
+    assert_eq!(resp.history.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_history() -> Result<()> {
+    let client = prepare_client()?;
+    let first = make_user(&client);
+    let second = make_user(&client);
+
+    let sync = |auth: &str, pkg: &common::HistoryPackage| -> common::HistoryPackage {
+        let resp = client
+            .post(uri!(RESOURCE.clone(), routes::resource::save_history(_, _)))
+            .json(pkg)
+            .header(Header::new(AUTHORIZATION.as_str(), auth.to_string()))
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        resp.into_json().unwrap()
+    };
+    let now = current_timestamp().unwrap();
+    let package = |page: i32, updated_at: Time, deleted_at: Time| {
+        let mut pkg = data::history_package();
+        pkg.history[0].created_at = now;
+        pkg.history[0].updated_at = updated_at;
+        pkg.history[0].deleted_at = deleted_at;
+        pkg.history[0].page = page;
+        pkg
+    };
+
+    // first device reads further
+    let resp = sync(&first, &package(5, now + 1000, 0));
+    assert_eq!(resp.history[0].page, 5);
+
+    // second device has stale progress, which doesn't overwrite newer one
+    let resp = sync(&second, &package(1, now, 0));
+    assert_eq!(resp.history[0].page, 5);
+    assert_eq!(resp.history[0].updated_at, now + 1000);
+
+    // newer progress from second device wins
+    let resp = sync(&second, &package(7, now + 2000, 0));
+    assert_eq!(resp.history[0].page, 7);
+    let resp = sync(&first, &package(5, now + 1000, 0));
+    assert_eq!(resp.history[0].page, 7);
+
+    // deletion is not reverted by stale update
+    let resp = sync(&first, &package(7, now + 2000, now + 3000));
+    assert_eq!(resp.history[0].deleted_at, now + 3000);
+    let resp = sync(&second, &package(8, now + 2500, 0));
+    assert_eq!(resp.history[0].deleted_at, now + 3000);
+    assert_eq!(resp.history[0].page, 7);
+
+    // stale item sent in delta sync is returned with server version
+    let mut pkg = package(1, now, 0);
+    pkg.timestamp = Some(current_timestamp().unwrap());
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_history(Some(true), _)
+        ))
+        .json(&pkg)
+        .header(Header::new(AUTHORIZATION.as_str(), second))
+        .dispatch();
+    let resp: common::HistoryPackage = resp.into_json().unwrap();
+    assert_eq!(resp.history.len(), 1);
+    assert_eq!(resp.history[0].deleted_at, now + 3000);
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_conflict() -> Result<()> {
+    let client = prepare_client()?;
+    let first = make_user(&client);
+    let second = make_user(&client);
+
+    let sync = |auth: &str, pkg: &common::HistoryPackage, strict: bool| {
+        client
+            .post(uri!(
+                RESOURCE.clone(),
+                routes::resource::save_history(_, Some(strict))
+            ))
+            .json(pkg)
+            .header(Header::new(AUTHORIZATION.as_str(), auth.to_string()))
+            .dispatch()
+    };
+
+    let mut pkg = data::history_package();
+    pkg.timestamp = None;
+    let resp = sync(&first, &pkg, true);
+    assert_eq!(resp.status(), Status::Ok);
+    assert!(resp.headers().get_one("X-Sync-Conflict").is_none());
+    let first_timestamp = resp
+        .into_json::<common::HistoryPackage>()
+        .unwrap()
+        .timestamp;
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+
+    // second device doesn't know about first one, data is merged
+    let mut pkg = data::history_package();
+    pkg.history[0].manga_id = 2;
+    pkg.history[0].manga.id = 2;
+    pkg.timestamp = None;
+    let resp = sync(&second, &pkg, false);
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(resp.headers().get_one("X-Sync-Conflict"), Some("true"));
+    let resp: common::HistoryPackage = resp.into_json().unwrap();
+    assert_eq!(resp.history.len(), 2);
+
+    // in strict mode outdated client gets conflict and nothing is saved
+    let mut pkg = data::history_package();
+    pkg.history[0].manga_id = 3;
+    pkg.history[0].manga.id = 3;
+    pkg.timestamp = first_timestamp;
+    let resp = sync(&first, &pkg, true);
+    assert_eq!(resp.status(), Status::Conflict);
+
+    let resp = client
+        .get(uri!(RESOURCE.clone(), routes::resource::get_history(_)))
+        .header(Header::new(AUTHORIZATION.as_str(), first.clone()))
+        .dispatch();
+    let resp: common::HistoryPackage = resp.into_json().unwrap();
+    assert_eq!(resp.history.len(), 2);
+
+    // after receiving changes client can sync
+    pkg.timestamp = resp.timestamp;
+    let resp = sync(&first, &pkg, true);
+    assert_eq!(resp.status(), Status::Ok);
+    assert!(resp.headers().get_one("X-Sync-Conflict").is_none());
+    let resp: common::HistoryPackage = resp.into_json().unwrap();
+    assert_eq!(resp.history.len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_favourites() -> Result<()> {
+    let client = prepare_client()?;
+    let first = make_user(&client);
+    let second = make_user(&client);
+
+    let sync = |auth: &str, pkg: &common::FavouritesPackage| -> common::FavouritesPackage {
+        let resp = client
+            .post(uri!(
+                RESOURCE.clone(),
+                routes::resource::save_favourites(_, _)
+            ))
+            .json(pkg)
+            .header(Header::new(AUTHORIZATION.as_str(), auth.to_string()))
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        resp.into_json().unwrap()
+    };
+    let now = current_timestamp().unwrap();
+    let package = |title: &str, deleted_at: Time| {
+        let mut pkg = data::favourites_package();
+        pkg.categories[0].created_at = now;
+        pkg.categories[0].title = title.to_string();
+        pkg.categories[0].deleted_at = deleted_at;
+        pkg.favourites[0].created_at = now;
+        pkg.favourites[0].deleted_at = deleted_at;
+        pkg
+    };
+
+    let resp = sync(&first, &package("first", 0));
+    assert_eq!(resp.categories[0].title, "first");
+    assert_eq!(resp.favourites[0].deleted_at, 0);
+
+    // second device removes category with favourite
+    let resp = sync(&second, &package("second", now + 1000));
+    assert_eq!(resp.categories[0].deleted_at, now + 1000);
+    assert_eq!(resp.favourites[0].deleted_at, now + 1000);
+
+    // first device doesn't know about it, but can't restore them
+    let resp = sync(&first, &package("first", 0));
+    assert_eq!(resp.categories[0].title, "second");
+    assert_eq!(resp.categories[0].deleted_at, now + 1000);
+    assert_eq!(resp.favourites[0].deleted_at, now + 1000);
+
+    // adding again later works
+    let mut pkg = package("again", 0);
+    pkg.categories[0].created_at = now + 2000;
+    pkg.favourites[0].created_at = now + 2000;
+    let resp = sync(&first, &pkg);
+    assert_eq!(resp.categories[0].title, "again");
+    assert_eq!(resp.categories[0].deleted_at, 0);
+    assert_eq!(resp.favourites[0].deleted_at, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_import_backup() -> Result<()> {
+    use std::io::Write;
+
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let manga = r#"{
+        "id": 1, "title": "test", "alt_title": null, "url": "kotatsu://test",
+        "public_url": "http://example.com/test", "rating": 2.3, "nsfw": true,
+        "cover_url": "http://example.com/cover", "large_cover_url": null,
+        "state": "FINISHED", "author": "Author", "source": "source",
+        "tags": [{"id": 1, "title": "Test", "key": "test", "source": "source", "pinned": false}]
+    }"#;
+    let entries = [
+        (
+            "index",
+            r#"[{"app_id": "org.koitharu.kotatsu", "app_version": 800}]"#.to_string(),
+        ),
+        (
+            "categories",
+            r#"[{"category_id": 1, "created_at": 10, "sort_key": 0, "title": "test",
+                "order": "NEWEST", "track": false, "show_in_lib": true}]"#
+                .to_string(),
+        ),
+        (
+            "favourites",
+            format!(
+                r#"[{{"manga_id": 1, "category_id": 1, "sort_key": 1, "pinned": false,
+                    "created_at": 20, "manga": {manga}}},
+                {{"manga_id": 1, "category_id": 2, "created_at": 20, "manga": {manga}}},
+                {{"manga_id": 3, "category_id": 1, "created_at": 20}}]"#
+            ),
+        ),
+        (
+            "history",
+            format!(
+                r#"[{{"manga_id": 1, "created_at": 30, "updated_at": 40, "chapter_id": 5,
+                    "page": 6, "scroll": 0.5, "percent": 0.25, "manga": {manga}}}]"#
+            ),
+        ),
+    ];
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, content) in entries {
+        archive.start_file(name, zip::write::SimpleFileOptions::default())?;
+        archive.write_all(content.as_bytes())?;
+    }
+    let archive = archive.finish()?.into_inner();
+
+    let resp = client
+        .post(uri!(RESOURCE.clone(), routes::resource::import_backup))
+        .body(&archive)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let report: response::BackupImport = resp.into_json().unwrap();
+    let count = |parsed, skipped| response::ImportCount { parsed, skipped };
+    assert_eq!(report.categories, count(1, 0));
+    assert_eq!(report.favourites, count(1, 2));
+    assert_eq!(report.history, count(1, 0));
+
+    let manga = || {
+        let mut manga = data::manga();
+        manga.content_rating = Some(common::ContentRating::Adult);
+        manga.tags.truncate(1);
+        manga
+    };
+
+    let resp = client
+        .get(uri!(RESOURCE.clone(), routes::resource::get_favourites(_)))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    let mut favourites: common::FavouritesPackage = resp.into_json().unwrap();
+    favourites.timestamp = None;
+    similar_asserts::assert_eq!(
+        favourites,
+        common::FavouritesPackage {
+            categories: vec![common::Category {
+                id: 1,
+                created_at: 10,
+                sort_key: 0,
+                track: false,
+                title: "test".to_string(),
+                order: "NEWEST".to_string(),
+                deleted_at: 0,
+                show_in_lib: true,
+            }],
+            favourites: vec![common::Favourite {
+                manga_id: 1,
+                manga: manga(),
+                category_id: 1,
+                sort_key: 1,
+                pinned: false,
+                created_at: 20,
+                deleted_at: 0,
+            }],
+            timestamp: None,
+        }
+    );
+
+    let resp = client
+        .get(uri!(RESOURCE.clone(), routes::resource::get_history(_)))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    let mut history: common::HistoryPackage = resp.into_json().unwrap();
+    history.timestamp = None;
+    similar_asserts::assert_eq!(
+        history,
+        common::HistoryPackage {
+            history: vec![common::History {
+                manga_id: 1,
+                manga: manga(),
+                created_at: 30,
+                updated_at: 40,
+                chapter_id: 5,
+                page: 6,
+                scroll: 0.5,
+                percent: 0.25,
+                chapters: -1,
+                deleted_at: 0,
+            }],
+            timestamp: None,
+        }
+    );
+
+    let resp = client
+        .post(uri!(RESOURCE.clone(), routes::resource::import_backup))
+        .body("not a zip")
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    Ok(())
+}
+
+#[test]
+fn test_import_backup_too_large() -> Result<()> {
+    use rocket::data::ToByteUnit;
+    use std::io::Write;
+
+    let client = prepare_client_with(|c| c.server.limits.backup = 1.kibibytes())?;
+    let auth = make_user(&client);
+
+    // small archive, which is too large when unpacked
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    archive.start_file("favourites", zip::write::SimpleFileOptions::default())?;
+    archive.write_all(format!("[{}]", " ".repeat(100_000)).as_bytes())?;
+    let archive = archive.finish()?.into_inner();
+    assert!(archive.len() < 1024);
+
+    let resp = client
+        .post(uri!(RESOURCE.clone(), routes::resource::import_backup))
+        .body(&archive)
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(
+        resp.into_string().unwrap(),
+        "invalid backup: archive is too large when unpacked"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_export() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let mut favourites = data::favourites_package();
+    favourites.favourites[0].deleted_at = 0;
+    favourites.favourites.push(data::favourite(2));
+    // deleted category, which is kept because of live favourite
+    let mut category = data::category(2);
+    category.deleted_at = current_timestamp().unwrap();
+    favourites.categories.push(category);
+    let mut favourite = data::favourite(3);
+    favourite.category_id = 2;
+    favourite.deleted_at = 0;
+    favourites.favourites.push(favourite);
+    let history = data::history_package();
+
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&favourites)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_history(Some(true), _)
+        ))
+        .json(&history)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .get(uri!(routes::base::export))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(resp.content_type(), Some(ContentType::ZIP));
+    let archive = resp.into_bytes().unwrap();
+
+    // deleted favourite and favourite in deleted category are not exported
+    favourites.categories.truncate(1);
+    favourites.favourites.truncate(1);
+    favourites.timestamp = None;
+    let mut history = history;
+    history.timestamp = None;
+
+    let backup = crate::backup::read(&archive, archive.len() as u64)?;
+    assert_eq!(backup.report.favourites.skipped, 0);
+    similar_asserts::assert_eq!(backup.favourites, favourites);
+    similar_asserts::assert_eq!(backup.history, history);
+
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_dump() -> Result<()> {
+    let (db_conf, db) = get_db()?;
+    let client = prepare_client_with_conf_and_db(true, db_conf, db.clone())?;
+    let auth = make_user(&client);
+
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&data::favourites_package())
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client
+        .post(uri!(RESOURCE.clone(), routes::resource::save_history(_, _)))
+        .json(&data::history_package())
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let mut dump = Vec::new();
+    let count = db.dump(&mut dump)?;
+    // user, session, refresh token, manga, tag, manga tag, category,
+    // favourite, history
+    assert!(count >= 9, "dumped only {count} rows");
+
+    let (_, restored) = get_db()?;
+    assert_eq!(restored.load(dump.as_slice())?, count);
+    let mut restored_dump = Vec::new();
+    restored.dump(&mut restored_dump)?;
+    similar_asserts::assert_eq!(
+        String::from_utf8(restored_dump)?,
+        String::from_utf8(dump.clone())?
+    );
+
+    // loading into database with data would mix two servers
+    assert!(restored.load(dump.as_slice()).is_err());
+
+    Ok(())
+}
+
+/// Dump of database, created by original server, which has only schema of
+/// its migrations
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_dump_original_schema() -> Result<()> {
+    use diesel::{Connection, SqliteConnection, connection::SimpleConnection};
+
+    /// First migration, which is not applied by original server
+    const FIRST_NEW_MIGRATION: &str = "20261018110000";
+
+    let (db_conf, db) = get_db()?;
+    while db.revert_migration()? != FIRST_NEW_MIGRATION {}
+    drop(db);
+
+    SqliteConnection::establish(&db_conf.url)?.batch_execute(
+        "insert into users (id, email, password_hash) values (1, 'test@example.com', 'hash');
+        insert into manga (id, title, url, public_url, rating, cover_url, source)
+            values (1, 'Title', 'url', 'public_url', 0.5, 'cover_url', 'SOURCE');
+        insert into tags (id, title, `key`, source) values (1, 'Tag', 'tag', 'SOURCE');
+        insert into manga_tags (manga_id, tag_id) values (1, 1);
+        insert into categories (id, created_at, sort_key, title, `order`, user_id, track, show_in_lib, deleted_at)
+            values (1, 1, 0, 'Category', 'NEWEST', 1, 1, 1, 0);
+        insert into favourites (manga_id, category_id, sort_key, created_at, deleted_at, user_id)
+            values (1, 1, 0, 1, 0, 1);
+        insert into history (manga_id, created_at, updated_at, chapter_id, page, scroll, percent, chapters, deleted_at, user_id)
+            values (1, 1, 1, 1, 0, 0, 0.5, 10, 0, 1);",
+    )?;
+
+    // applies the rest of migrations
+    let db = DB::new(db_conf)?;
+    let mut dump = Vec::new();
+    assert_eq!(db.dump(&mut dump)?, 7);
+
+    let (_, restored) = get_db()?;
+    assert_eq!(restored.load(dump.as_slice())?, 7);
+    assert_eq!(
+        restored
+            .get_user_by_email("test@example.com")?
+            .map(|u| u.id),
+        Some(1)
+    );
+
+    Ok(())
+}
+
+/// Save, which waited for other one to commit, should mark rows as modified
+/// after sync timestamp of the other one, so they are received in delta sync
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sync_interleaved_saves() -> Result<()> {
+    use diesel::{Connection, SqliteConnection, connection::SimpleConnection};
+
+    let (db_conf, db) = get_db()?;
+    let user = db.create_user("test@example.com", "hash")?;
+
+    // other save holds write lock
+    let mut other = SqliteConnection::establish(&db_conf.url)?;
+    other.batch_execute("BEGIN IMMEDIATE")?;
+
+    let synchronized = std::thread::scope(|s| -> Result<Time> {
+        let save = s.spawn(|| db.add_history_package(&data::history_package(), user.id, false));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        let synchronized = current_timestamp().unwrap();
+        other.batch_execute(&format!(
+            "UPDATE users SET history_sync_timestamp = {synchronized} WHERE id = {};
+            COMMIT",
+            user.id
+        ))?;
+        assert!(save.join().unwrap()?, "save should see other save");
+        Ok(synchronized)
+    })?;
+
+    let history = db.load_history_package(user.id, Some(synchronized))?;
+    assert_eq!(history.history.len(), 1);
+    assert!(history.timestamp > Some(synchronized));
+
+    Ok(())
+}
+
+#[test]
+fn test_list_manga_invalid_params() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let req = client
+        .get("/manga")
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()));
+
+    let resp = req.clone().dispatch();
+
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "offset is required");
+
+    let mut req = req;
+    req.set_uri(uri!("/manga?offset=0"));
+    let resp = req.clone().dispatch();
+
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "limit is required");
+
+    req.set_uri(uri!("/manga?offset=0&limit=10000"));
+    let resp = req.clone().dispatch();
+
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "max limit is 1000");
+
+    req.set_uri(uri!("/manga?offset=0&limit=10&cursor="));
+    let resp = req.clone().dispatch();
+
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(
+        resp.into_string().unwrap(),
+        "offset and cursor can't be used together"
+    );
+
+    req.set_uri(uri!("/manga?limit=10&cursor=abc"));
+    let resp = req.clone().dispatch();
+
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "invalid cursor");
+
+    Ok(())
+}
+
+#[test]
+fn test_list_manga() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    // prepare
+
+    let req = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()));
+
+    let data = data::favourites_package();
+    let resp = req.clone().json(&data).dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let new_manga_id = 2;
+
+    let mut data = data;
+    data.favourites[0].manga_id = new_manga_id;
+    data.favourites[0].manga.id = new_manga_id;
+    let resp = req.clone().json(&data).dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    // check
+
+    let req = client
+        .get(uri!(routes::base::list_manga(
+            Some(0),
+            Some(1),
+            _,
+            request::MangaFilter::default()
+        )))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()));
+
+    let resp = req.clone().dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let resp: Vec<common::Manga> = resp.into_json().unwrap();
+    assert_eq!(resp.len(), 1);
+
+    let mut manga = data::manga();
+    similar_asserts::assert_eq!(resp[0], manga);
+
+    let mut req = req;
+    req.set_uri(uri!(routes::base::list_manga(
+        Some(1),
+        Some(2),
+        _,
+        request::MangaFilter::default()
+    )));
+    let resp = req.clone().dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp: Vec<common::Manga> = resp.into_json().unwrap();
+    assert_eq!(resp.len(), 1);
+
+    manga.id = new_manga_id;
+    similar_asserts::assert_eq!(resp[0], manga);
+
+    Ok(())
+}
+
+#[test]
+fn test_list_manga_cursor() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    // saving in reverse order with different tags, to check ordering and
+    // that tags belong to their manga
+    let mut data = data::favourites_package();
+    for id in [3, 2] {
+        let mut favourite = data::favourite(id);
+        favourite.manga.tags.truncate(1);
+        favourite.manga.tags[0].id = id + 10;
+        data.favourites.push(favourite);
+    }
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&data)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let mut expected: Vec<_> = data.favourites.into_iter().map(|f| f.manga).collect();
+    expected.sort_by_key(|m| m.id);
+
+    let resp = client
+        .get(uri!(routes::base::list_manga(
+            _,
+            Some(2),
+            Some(""),
+            request::MangaFilter::default()
+        )))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let page: response::MangaPage = resp.into_json().unwrap();
+    similar_asserts::assert_eq!(page.manga, expected[..2]);
+    assert_eq!(page.next_cursor.as_deref(), Some("2"));
+
+    let resp = client
+        .get(uri!(routes::base::list_manga(
+            _,
+            Some(2),
+            page.next_cursor.as_deref(),
+            request::MangaFilter::default()
+        )))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let page: response::MangaPage = resp.into_json().unwrap();
+    similar_asserts::assert_eq!(page.manga, expected[2..]);
+    assert_eq!(page.next_cursor, None);
+
+    // offset pagination uses the same order
+    let resp = client
+        .get(uri!(routes::base::list_manga(
+            Some(1),
+            Some(2),
+            _,
+            request::MangaFilter::default()
+        )))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let list: Vec<common::Manga> = resp.into_json().unwrap();
+    similar_asserts::assert_eq!(list, expected[1..]);
+
+    Ok(())
+}
+
+#[test]
+fn test_list_manga_filter() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let mut data = data::favourites_package();
+    let mut favourite = data::favourite(2);
+    let manga = &mut favourite.manga;
+    manga.title = "Other 100%".to_string();
+    manga.alt_title = Some("Alt".to_string());
+    manga.source = "other".to_string();
+    manga.state = Some(common::MangaState::Ongoing);
+    manga.content_rating = Some(common::ContentRating::Safe);
+    manga.author = Some("Other author".to_string());
+    manga.tags.truncate(1);
+    manga.tags[0].id = 3;
+    manga.tags[0].key = "other".to_string();
+    data.favourites.push(favourite);
+
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&data)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let list = |query: &str| -> Vec<i64> {
+        let resp = client
+            .get(format!("/manga?offset=0&limit=10&{query}"))
+            .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok, "{query}");
+        let list: Vec<common::Manga> = resp.into_json().unwrap();
+        list.into_iter().map(|m| m.id).collect()
+    };
+
+    assert_eq!(list(""), vec![1, 2]);
+    assert_eq!(list("q=tes"), vec![1]);
+    assert_eq!(list("q=alt"), vec![2]);
+    assert_eq!(list("q=100%25"), vec![2]);
+    // wildcards are not special
+    assert_eq!(list("q=%25"), vec![2]);
+    assert_eq!(list("q=_"), Vec::<i64>::new());
+    assert_eq!(list("source=other"), vec![2]);
+    assert_eq!(list("state=FINISHED"), vec![1]);
+    assert_eq!(list("content_rating=SAFE"), vec![2]);
+    assert_eq!(list("author=Author"), vec![1]);
+    assert_eq!(list("tag=test2"), vec![1]);
+    assert_eq!(list("tag=other&source=other"), vec![2]);
+    assert_eq!(list("tag=other&source=source"), Vec::<i64>::new());
+
+    let resp = client
+        .get("/manga?offset=0&limit=10&state=unknown")
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "unknown state");
+
+    let resp = client
+        .get("/manga?offset=0&limit=10&content_rating=unknown")
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "unknown content_rating");
+
+    Ok(())
+}
+
+#[test]
+fn test_search_manga() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let mut data = data::favourites_package();
+    for (id, title, alt_title) in [
+        (2, "Tensei Shitara", Some("That Time I Got Reincarnated")),
+        (3, "Reincarnated \"as\" sword", None),
+    ] {
+        let mut favourite = data::favourite(id);
+        favourite.manga.title = title.to_string();
+        favourite.manga.alt_title = alt_title.map(str::to_string);
+        data.favourites.push(favourite);
+    }
+    let req = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()));
+    let resp = req.clone().json(&data).dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let search = |q: &str| -> Vec<i64> {
+        let resp = client
+            .get(uri!(routes::base::search_manga(Some(q), _)))
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok, "{q}");
+        let list: Vec<common::Manga> = resp.into_json().unwrap();
+        list.into_iter().map(|m| m.id).collect()
+    };
+
+    assert_eq!(search("test"), vec![1]);
+    assert_eq!(search("reincarnated sword"), vec![3]);
+    assert_eq!(search("reinc"), vec![3, 2]);
+    assert_eq!(search("tensei"), vec![2]);
+    // query syntax is not used
+    assert_eq!(search("\"as\" OR"), Vec::<i64>::new());
+    assert_eq!(search("sword*"), vec![3]);
+
+    // index is updated with manga
+    data.favourites[1].manga.title = "Renamed".to_string();
+    data.favourites[1].manga.alt_title = None;
+    let resp = req.json(&data).dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    assert_eq!(search("tensei"), Vec::<i64>::new());
+    assert_eq!(search("renamed"), vec![2]);
+
+    let resp = client
+        .get(uri!(routes::base::search_manga(Some(" "), _)))
+        .dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+    assert_eq!(resp.into_string().unwrap(), "q is required");
+
+    // tags are loaded
+    let resp = client
+        .get(uri!(routes::base::search_manga(Some("test"), _)))
+        .dispatch();
+    let list: Vec<common::Manga> = resp.into_json().unwrap();
+    similar_asserts::assert_eq!(list, vec![data::manga()]);
+
+    Ok(())
+}
+
+#[test]
+fn test_get_manga() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let data = data::favourites_package();
+
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&data)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+
+    assert_eq!(resp.status(), Status::Ok);
+
+    let manga = &data.favourites[0].manga;
+
+    let resp = client
+        .get(uri!(routes::base::get_manga(id = manga.id)))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp: common::Manga = resp.into_json().unwrap();
+    similar_asserts::assert_eq!(resp, *manga);
+
+    Ok(())
+}
+
+#[test]
+fn test_tags() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let mut data = data::favourites_package();
+    let mut favourite = data::favourite(2);
+    favourite.manga.tags.remove(0);
+    favourite.manga.tags.push(common::MangaTag {
+        id: 3,
+        title: "Other".to_string(),
+        key: "other".to_string(),
+        source: "other".to_string(),
+    });
+    data.favourites.push(favourite);
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&data)
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let list = |source: Option<&str>, offset: Option<usize>| -> Vec<i64> {
+        let resp = client
+            .get(uri!(routes::base::list_tags(source, offset, Some(2))))
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        let tags: Vec<common::MangaTag> = resp.into_json().unwrap();
+        tags.into_iter().map(|t| t.id).collect()
+    };
+    assert_eq!(list(None, None), vec![1, 2]);
+    assert_eq!(list(None, Some(2)), vec![3]);
+    assert_eq!(list(Some("source"), None), vec![1, 2]);
+    assert_eq!(list(Some("other"), None), vec![3]);
+
+    let resp = client.get(uri!(routes::base::get_tag(1))).dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let tag: common::MangaTag = resp.into_json().unwrap();
+    assert_eq!(tag, data::manga().tags.remove(0));
+
+    let resp = client.get(uri!(routes::base::get_tag(4))).dispatch();
+    assert_eq!(resp.status(), Status::NotFound);
+
+    let manga = |id: i64| -> Vec<i64> {
+        let resp = client
+            .get(uri!(routes::base::list_tag_manga(id, _, _)))
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        let list: Vec<common::Manga> = resp.into_json().unwrap();
+        list.into_iter().map(|m| m.id).collect()
+    };
+    assert_eq!(manga(1), vec![1]);
+    assert_eq!(manga(2), vec![1, 2]);
+    assert_eq!(manga(3), vec![2]);
+
+    let resp = client
+        .get(uri!(routes::base::list_tag_manga(4, _, _)))
+        .dispatch();
+    assert_eq!(resp.status(), Status::NotFound);
+
+    Ok(())
+}
+
+#[test]
+fn test_empty_stats() -> Result<()> {
+    let client = prepare_client()?;
+
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::stats))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let resp: Stats = resp.into_json().unwrap();
+
+    assert_eq!(resp.db.users_count, 0);
+    assert_eq!(resp.db.manga_count, 0);
+    assert_eq!(resp.rate_limit.failed_attempts, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_admin_auth() -> Result<()> {
+    let client = prepare_client()?;
+
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::stats))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::info))
+        .header(bearer("wrong"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    // user token is not accepted
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::list_invites))
+        .header(Header::new(AUTHORIZATION.as_str(), make_user(&client)))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
+
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::info))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    Ok(())
+}
+
+#[test]
+fn test_admin_token_required() -> Result<()> {
+    let res = prepare_client_with(|c| c.server.admin_token = None);
+    assert!(res.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_stats() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let data = data::favourites_package();
+
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&data)
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::stats))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let resp: Stats = resp.into_json().unwrap();
+
+    assert_eq!(resp.db.users_count, 1);
+    assert_eq!(resp.db.manga_count, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_admin_users() -> Result<()> {
+    let client = prepare_client()?;
+    let auth = make_user(&client);
+
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&data::favourites_package())
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client
+        .post(uri!(RESOURCE.clone(), routes::resource::save_history(_, _)))
+        .json(&data::history_package())
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new("test2@example.com", "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .get(uri!(
+            ADMIN.clone(),
+            routes::admin::list_users(None::<usize>, None::<usize>)
+        ))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let users: Vec<admin::User> = resp.into_json().unwrap();
+    assert_eq!(users.len(), 2);
+    let user = &users[0];
+    assert_eq!(user.email, "test@example.com");
+    // favourite in test data is deleted
+    assert_eq!(user.favourites_count, 0);
+    assert_eq!(user.categories_count, 1);
+    assert_eq!(user.history_count, 1);
+    assert!(user.favourites_sync_timestamp.is_some());
+    assert!(user.disabled_at.is_none());
+    assert_eq!(users[1].email, "test2@example.com");
+    assert_eq!(users[1].history_count, 0);
+
+    let resp = client
+        .get(uri!(
+            ADMIN.clone(),
+            routes::admin::list_users(Some(1), Some(10))
+        ))
+        .header(admin_auth())
+        .dispatch();
+    let page: Vec<admin::User> = resp.into_json().unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].id, users[1].id);
+
+    let resp = client
+        .get(uri!(
+            ADMIN.clone(),
+            routes::admin::list_users(None::<usize>, Some(1001))
+        ))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::BadRequest);
+
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::get_user(user.id)))
+        .header(admin_auth())
         .dispatch();
-
     assert_eq!(resp.status(), Status::Ok);
+    let resp: admin::User = resp.into_json().unwrap();
+    assert_eq!(resp.email, user.email);
+    assert_eq!(resp.history_count, 1);
 
     let resp = client
-        .post(uri!(routes::base::auth))
-        .json(&request::Auth::new(&email, "asdf"))
+        .get(uri!(
+            ADMIN.clone(),
+            routes::admin::get_user(users[1].id + 1)
+        ))
+        .header(admin_auth())
         .dispatch();
-
-    assert_eq!(resp.status(), Status::BadRequest);
-    assert_eq!(resp.into_string().unwrap(), "Wrong password");
+    assert_eq!(resp.status(), Status::NotFound);
 
     Ok(())
 }
 
 #[test]
-fn test_sync_favourites() -> Result<()> {
+fn test_admin_disable_user() -> Result<()> {
     let client = prepare_client()?;
+    let email = "test@example.com";
     let auth = make_user(&client);
 
-    let data = data::favourites_package();
-
     let resp = client
-        .post(uri!(RESOURCE.clone(), routes::resource::save_favourites))
-        .json(&data)
+        .get(uri!(routes::base::me))
         .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
         .dispatch();
+    let me: response::Me = resp.into_json().unwrap();
 
-    assert_eq!(resp.status(), Status::Ok);
+    let resp = client
+        .post(uri!(ADMIN.clone(), routes::admin::disable_user(me.id)))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::NoContent);
 
+    // sessions are revoked
     let resp = client
-        .get(uri!(RESOURCE.clone(), routes::resource::get_favourites))
+        .get(uri!(routes::base::me))
         .header(Header::new(AUTHORIZATION.as_str(), auth))
         .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
 
-    assert_eq!(resp.status(), Status::Ok);
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Forbidden);
+    assert_eq!(resp.into_string().unwrap(), "User is disabled");
 
-    let mut data = data;
-    let sent_timestamp = data.timestamp.take();
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::get_user(me.id)))
+        .header(admin_auth())
+        .dispatch();
+    let user: admin::User = resp.into_json().unwrap();
+    assert!(user.disabled_at.is_some());
 
-    let mut resp: common::FavouritesPackage = resp.into_json().unwrap();
-    let timestamp = resp.timestamp.take();
+    let resp = client
+        .post(uri!(ADMIN.clone(), routes::admin::enable_user(me.id)))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::NoContent);
 
-    similar_asserts::assert_eq!(data, resp);
-    assert!(timestamp < current_timestamp());
-    if let Some(sent_timestamp) = sent_timestamp {
-        assert!(timestamp.is_some_and(|t| t > sent_timestamp));
-    } else {
-        assert!(timestamp.is_some());
-    }
+    let resp = client
+        .post(uri!(routes::base::auth))
+        .json(&request::Auth::new(email, "test"))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .post(uri!(ADMIN.clone(), routes::admin::disable_user(me.id + 1)))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::NotFound);
 
     Ok(())
 }
 
 #[test]
-// todo: test for sync which doesn't change data in db (which returns 204)
-fn test_sync_history() -> Result<()> {
+fn test_admin_delete_user() -> Result<()> {
     let client = prepare_client()?;
     let auth = make_user(&client);
 
-    let data = data::history_package();
-
     let resp = client
-        .post(uri!(RESOURCE.clone(), routes::resource::save_history))
-        .json(&data)
+        .get(uri!(routes::base::me))
         .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
         .dispatch();
+    let me: response::Me = resp.into_json().unwrap();
 
-    assert_eq!(resp.status(), Status::Ok);
+    let resp = client
+        .delete(uri!(ADMIN.clone(), routes::admin::delete_user(me.id)))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::NoContent);
 
     let resp = client
-        .get(uri!(RESOURCE.clone(), routes::resource::get_history))
+        .get(uri!(routes::base::me))
         .header(Header::new(AUTHORIZATION.as_str(), auth))
         .dispatch();
+    assert_eq!(resp.status(), Status::Unauthorized);
 
-    assert_eq!(resp.status(), Status::Ok);
-
-    let mut data = data;
-    let sent_timestamp = data.timestamp.take();
-
-    let mut resp: common::HistoryPackage = resp.into_json().unwrap();
-    let timestamp = resp.timestamp.take();
-
-    similar_asserts::assert_eq!(data, resp);
-    assert!(timestamp < current_timestamp());
-    if let Some(sent_timestamp) = sent_timestamp {
-        assert!(timestamp.is_some_and(|t| t > sent_timestamp));
-    } else {
-        assert!(timestamp.is_some());
-    }
+    let resp = client
+        .delete(uri!(ADMIN.clone(), routes::admin::delete_user(me.id)))
+        .header(admin_auth())
+        .dispatch();
+    assert_eq!(resp.status(), Status::NotFound);
 
     Ok(())
 }
 
 #[test]
-fn test_list_manga_invalid_params() -> Result<()> {
+fn test_gc() -> Result<()> {
     let client = prepare_client()?;
     let auth = make_user(&client);
+    // older than retention in test config
+    let old = current_timestamp().unwrap() - 2 * 60 * 1000;
 
-    let req = client
-        .get("/manga")
-        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()));
+    let mut favourites = data::favourites_package();
+    favourites.favourites[0].deleted_at = old;
+    let resp = client
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&favourites)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+    let mut history = data::history_package();
+    let resp = client
+        .post(uri!(RESOURCE.clone(), routes::resource::save_history(_, _)))
+        .json(&history)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
 
-    let resp = req.clone().dispatch();
+    let gc = || -> admin::GCStats {
+        let resp = client
+            .post(uri!(ADMIN.clone(), routes::admin::run_gc))
+            .header(admin_auth())
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        resp.into_json().unwrap()
+    };
 
-    assert_eq!(resp.status(), Status::BadRequest);
-    assert_eq!(resp.into_string().unwrap(), "offset is required");
+    // manga is still referenced by history
+    let stats = gc();
+    assert_eq!(stats.favourites, 1);
+    assert_eq!(stats.categories, 0);
+    assert_eq!(stats.history, 0);
+    assert_eq!(stats.manga, 0);
 
-    let mut req = req;
-    req.set_uri(uri!("/manga?offset=0"));
-    let resp = req.clone().dispatch();
+    let resp = client
+        .get(uri!(RESOURCE.clone(), routes::resource::get_favourites(_)))
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    let resp: common::FavouritesPackage = resp.into_json().unwrap();
+    assert!(resp.favourites.is_empty());
+    assert_eq!(resp.categories.len(), 1);
 
-    assert_eq!(resp.status(), Status::BadRequest);
-    assert_eq!(resp.into_string().unwrap(), "limit is required");
+    history.history[0].deleted_at = old;
+    let resp = client
+        .post(uri!(RESOURCE.clone(), routes::resource::save_history(_, _)))
+        .json(&history)
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
 
-    req.set_uri(uri!("/manga?offset=0&limit=10000"));
-    let resp = req.clone().dispatch();
+    let stats = gc();
+    assert_eq!(stats.history, 1);
+    assert_eq!(stats.manga, 1);
+    assert_eq!(stats.tags, 2);
 
-    assert_eq!(resp.status(), Status::BadRequest);
-    assert_eq!(resp.into_string().unwrap(), "max limit is 1000");
+    let resp = client.get(uri!(routes::base::get_manga(1))).dispatch();
+    assert_eq!(resp.status(), Status::NotFound);
+
+    // nothing to remove
+    let stats = gc();
+    assert_eq!(
+        stats.favourites + stats.history + stats.manga + stats.tags,
+        0
+    );
 
     Ok(())
 }
 
+#[cfg(feature = "sqlite")]
 #[test]
-fn test_list_manga() -> Result<()> {
-    let client = prepare_client()?;
-    let auth = make_user(&client);
-
-    // prepare
-
-    let req = client
-        .post(uri!(RESOURCE.clone(), routes::resource::save_favourites))
-        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()));
-
-    let data = data::favourites_package();
-    let resp = req.clone().json(&data).dispatch();
-    assert_eq!(resp.status(), Status::Ok);
-
-    let new_manga_id = 2;
-
-    let mut data = data;
-    data.favourites[0].manga_id = new_manga_id;
-    data.favourites[0].manga.id = new_manga_id;
-    let resp = req.clone().json(&data).dispatch();
-    assert_eq!(resp.status(), Status::Ok);
-
-    // check
-
-    let req = client
-        .get(uri!(routes::base::list_manga(Some(0), Some(1))))
-        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()));
+fn test_backup() -> Result<()> {
+    let dir = "target/test-backups";
+    match std::fs::remove_dir_all(dir) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e.into()),
+    }
 
-    let resp = req.clone().dispatch();
-    assert_eq!(resp.status(), Status::Ok);
-    let resp: Vec<common::Manga> = resp.into_json().unwrap();
-    assert_eq!(resp.len(), 1);
+    let client = prepare_client_with(|c| {
+        c.backup.dir = Some(dir.to_string());
+        c.backup.keep = 2;
+    })?;
+    make_user(&client);
 
-    let mut manga = data::manga();
-    similar_asserts::assert_eq!(resp[0], manga);
+    let stats = || -> Stats {
+        let resp = client
+            .get(uri!(ADMIN.clone(), routes::admin::stats))
+            .header(admin_auth())
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        resp.into_json().unwrap()
+    };
+    assert_eq!(stats().last_backup_at, None);
 
-    let mut req = req;
-    req.set_uri(uri!(routes::base::list_manga(Some(1), Some(2))));
-    let resp = req.clone().dispatch();
+    let mut backups = vec![];
+    for _ in 0..3 {
+        // backups are named by time in milliseconds
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let resp = client
+            .post(uri!(ADMIN.clone(), routes::admin::run_backup))
+            .header(admin_auth())
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        let backup: admin::Backup = resp.into_json().unwrap();
+        assert!(backup.size > 0);
+        backups.push(backup);
+    }
+    assert_eq!(stats().last_backup_at, Some(backups[2].created_at));
+
+    // oldest is rotated
+    let mut files: Vec<_> = std::fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path().display().to_string()))
+        .collect::<Result<_, _>>()?;
+    files.sort();
+    let expected: Vec<_> = backups[1..].iter().map(|b| b.path.clone()).collect();
+    assert_eq!(files, expected);
+
+    // backup is a complete database
+    let db = DB::new(crate::config::ConfDB {
+        url: backups[2].path.clone(),
+    })?;
+    assert_eq!(db.stats()?.users_count, 1);
+
+    // job is registered only when backups are enabled
+    let resp = client
+        .post(uri!(ADMIN.clone(), routes::admin::run_job("backup")))
+        .header(admin_auth())
+        .dispatch();
     assert_eq!(resp.status(), Status::Ok);
+    let job: admin::JobStatus = resp.into_json().unwrap();
+    let last_backup_at = stats().last_backup_at;
 
-    let resp: Vec<common::Manga> = resp.into_json().unwrap();
-    assert_eq!(resp.len(), 1);
-
-    manga.id = new_manga_id;
-    similar_asserts::assert_eq!(resp[0], manga);
+    // after restart, the next backup is scheduled after the last one
+    let client = prepare_client_with(|c| c.backup.dir = Some(dir.to_string()))?;
+    let resp = client
+        .get(uri!(ADMIN.clone(), routes::admin::list_jobs))
+        .header(admin_auth())
+        .dispatch();
+    let jobs: Vec<admin::JobStatus> = resp.into_json().unwrap();
+    let restarted = jobs.iter().find(|j| j.name == "backup").unwrap();
+    assert_eq!(restarted.runs, 0);
+    assert_eq!(restarted.last_finished_at, last_backup_at);
+    assert!(restarted.last_finished_at <= job.last_finished_at);
 
     Ok(())
 }
 
 #[test]
-fn test_get_manga() -> Result<()> {
+fn test_backup_disabled() -> Result<()> {
     let client = prepare_client()?;
-    let auth = make_user(&client);
-
-    let data = data::favourites_package();
 
     let resp = client
-        .post(uri!(RESOURCE.clone(), routes::resource::save_favourites))
-        .json(&data)
-        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .post(uri!(ADMIN.clone(), routes::admin::run_backup))
+        .header(admin_auth())
         .dispatch();
-
-    assert_eq!(resp.status(), Status::Ok);
-
-    let manga = &data.favourites[0].manga;
-
+    assert_eq!(resp.status(), Status::BadRequest);
     let resp = client
-        .get(uri!(routes::base::get_manga(id = manga.id)))
-        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .post(uri!(ADMIN.clone(), routes::admin::run_job("backup")))
+        .header(admin_auth())
         .dispatch();
-
-    assert_eq!(resp.status(), Status::Ok);
-
-    let resp: common::Manga = resp.into_json().unwrap();
-    similar_asserts::assert_eq!(resp, *manga);
+    assert_eq!(resp.status(), Status::NotFound);
 
     Ok(())
 }
 
 #[test]
-fn test_empty_stats() -> Result<()> {
+fn test_gc_referenced_category() -> Result<()> {
     let client = prepare_client()?;
+    let auth = make_user(&client);
+    let old = current_timestamp().unwrap() - 2 * 60 * 1000;
 
+    // category is deleted long ago, but favourite in it is not
+    let mut favourites = data::favourites_package();
+    favourites.categories[0].deleted_at = old;
+    favourites.favourites[0].deleted_at = 0;
     let resp = client
-        .get(uri!(ADMIN.clone(), routes::admin::stats))
+        .post(uri!(
+            RESOURCE.clone(),
+            routes::resource::save_favourites(_, _)
+        ))
+        .json(&favourites)
+        .header(Header::new(AUTHORIZATION.as_str(), auth.clone()))
+        .dispatch();
+    assert_eq!(resp.status(), Status::Ok);
+
+    let resp = client
+        .post(uri!(ADMIN.clone(), routes::admin::run_gc))
+        .header(admin_auth())
         .dispatch();
     assert_eq!(resp.status(), Status::Ok);
-    let resp: DBStats = resp.into_json().unwrap();
+    let stats: admin::GCStats = resp.into_json().unwrap();
+    assert_eq!(stats.categories, 0);
+    assert_eq!(stats.manga, 0);
 
-    assert_eq!(resp.users_count, 0);
-    assert_eq!(resp.manga_count, 0);
+    let resp = client
+        .get(uri!(RESOURCE.clone(), routes::resource::get_favourites(_)))
+        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .dispatch();
+    let resp: common::FavouritesPackage = resp.into_json().unwrap();
+    assert_eq!(resp.categories.len(), 1);
+    assert_eq!(resp.favourites.len(), 1);
+    assert_eq!(resp.favourites[0].category_id, resp.categories[0].id);
 
     Ok(())
 }
 
 #[test]
-fn test_stats() -> Result<()> {
+fn test_jobs() -> Result<()> {
     let client = prepare_client()?;
-    let auth = make_user(&client);
-
-    let data = data::favourites_package();
+    make_user(&client);
 
     let resp = client
-        .post(uri!(RESOURCE.clone(), routes::resource::save_favourites))
-        .json(&data)
-        .header(Header::new(AUTHORIZATION.as_str(), auth))
+        .get(uri!(ADMIN.clone(), routes::admin::list_jobs))
+        .header(admin_auth())
         .dispatch();
-
     assert_eq!(resp.status(), Status::Ok);
+    let jobs: Vec<admin::JobStatus> = resp.into_json().unwrap();
+    let names: Vec<_> = jobs.iter().map(|j| j.name.as_str()).collect();
+    assert_eq!(names, ["gc", "prune_sessions", "optimize_db"]);
+    // disabled in test config
+    assert!(jobs.iter().all(|j| j.interval.is_none() && j.runs == 0));
+
+    for name in names {
+        let resp = client
+            .post(uri!(ADMIN.clone(), routes::admin::run_job(name)))
+            .header(admin_auth())
+            .dispatch();
+        assert_eq!(resp.status(), Status::Ok);
+        let job: admin::JobStatus = resp.into_json().unwrap();
+        assert_eq!(job.runs, 1);
+        assert!(!job.running);
+        assert!(job.last_error.is_none());
+        assert!(job.last_finished_at >= job.last_started_at);
+    }
 
     let resp = client
-        .get(uri!(ADMIN.clone(), routes::admin::stats))
+        .post(uri!(ADMIN.clone(), routes::admin::run_job("unknown")))
+        .header(admin_auth())
         .dispatch();
-    assert_eq!(resp.status(), Status::Ok);
-    let resp: DBStats = resp.into_json().unwrap();
-
-    assert_eq!(resp.users_count, 1);
-    assert_eq!(resp.manga_count, 1);
+    assert_eq!(resp.status(), Status::NotFound);
 
     Ok(())
 }
@@ -374,28 +2499,39 @@ mod data {
     };
 
     pub fn favourites_package() -> common::FavouritesPackage {
-        let now = current_timestamp().unwrap();
         common::FavouritesPackage {
-            categories: vec![common::Category {
-                id: 1,
-                created_at: now,
-                sort_key: 0,
-                track: false,
-                title: "test".to_string(),
-                order: "NEWEST".to_string(),
-                deleted_at: 0,
-                show_in_lib: true,
-            }],
-            favourites: vec![common::Favourite {
-                manga_id: 1,
-                manga: manga(),
-                category_id: 1,
-                sort_key: 1,
-                pinned: false,
-                created_at: now,
-                deleted_at: now,
-            }],
-            timestamp: Some(now),
+            categories: vec![category(1)],
+            favourites: vec![favourite(1)],
+            timestamp: Some(current_timestamp().unwrap()),
+        }
+    }
+    pub fn category(id: i64) -> common::Category {
+        common::Category {
+            id,
+            created_at: current_timestamp().unwrap(),
+            sort_key: 0,
+            track: false,
+            title: "test".to_string(),
+            order: "NEWEST".to_string(),
+            deleted_at: 0,
+            show_in_lib: true,
+        }
+    }
+    /// Favourite in the first category, with manga which differs from
+    /// `manga()` only by id
+    pub fn favourite(manga_id: i64) -> common::Favourite {
+        let now = current_timestamp().unwrap();
+        common::Favourite {
+            manga_id,
+            manga: common::Manga {
+                id: manga_id,
+                ..manga()
+            },
+            category_id: 1,
+            sort_key: 1,
+            pinned: false,
+            created_at: now,
+            deleted_at: now,
         }
     }
     pub fn history_package() -> common::HistoryPackage {
@@ -450,10 +2586,18 @@ mod data {
 
 pub mod utils {
     use anyhow::Result;
-    use rocket::{data::ToByteUnit, http::Status, local::blocking::Client, uri};
+    use rocket::{
+        data::ToByteUnit,
+        http::{Header, Status, hyper::header::AUTHORIZATION},
+        local::blocking::Client,
+        uri,
+    };
 
     use crate::{
-        config::{Conf, ConfDB, ConfJWT, ConfServer, ConfServerLimits},
+        config::{
+            Conf, ConfBackup, ConfDB, ConfGC, ConfJWT, ConfJobs, ConfServer, ConfServerLimits,
+            ConfServerRateLimit,
+        },
         db::conn::DB,
         models::{request, response},
         rocket, routes,
@@ -516,22 +2660,72 @@ pub mod utils {
         db_conf: ConfDB,
         db: DB,
     ) -> Result<Client> {
-        let config = Conf {
+        let mut config = test_config(db_conf);
+        config.server.allow_new_register = allow_new_register;
+        Ok(Client::untracked(rocket(config, db)?)?)
+    }
+
+    /// Prepare client with modified default test config
+    pub fn prepare_client_with(modify: impl FnOnce(&mut Conf)) -> Result<Client> {
+        let (db_conf, db) = get_db()?;
+        let mut config = test_config(db_conf);
+        modify(&mut config);
+        Ok(Client::untracked(rocket(config, db)?)?)
+    }
+
+    pub fn test_config(db_conf: ConfDB) -> Conf {
+        Conf {
             server: ConfServer {
                 port: 8080,
                 admin_api: Some("/admin".to_string()),
-                allow_new_register,
+                admin_token: Some("admin".to_string()),
+                ip_header: None,
+                allow_new_register: true,
+                separate_register: false,
                 limits: ConfServerLimits {
                     json: 4.mebibytes(),
+                    backup: 4.mebibytes(),
+                },
+                rate_limit: ConfServerRateLimit {
+                    enabled: true,
+                    window: 60,
+                    max_per_email: 5,
+                    max_per_ip: 20,
+                    lockout: 60,
+                    max_lockout: 60 * 60,
                 },
             },
-            db: db_conf.clone(),
+            db: db_conf,
             jwt: ConfJWT {
                 secret: "test".to_string(),
                 issuer: "http://example.com".to_string(),
+                access_lifetime: 60,
+                refresh_lifetime: 60 * 60,
             },
-        };
-        Ok(Client::untracked(rocket(config, db)?)?)
+            // started manually in tests
+            gc: ConfGC {
+                enabled: false,
+                retention: 60,
+                interval: 60,
+            },
+            jobs: ConfJobs {
+                prune_sessions_interval: 0,
+                optimize_db_interval: 0,
+            },
+            backup: ConfBackup {
+                dir: None,
+                interval: 0,
+                keep: 7,
+            },
+        }
+    }
+
+    pub fn admin_auth() -> Header<'static> {
+        bearer("admin")
+    }
+
+    pub fn bearer(token: &str) -> Header<'static> {
+        Header::new(AUTHORIZATION.as_str(), format!("Bearer {token}"))
     }
 
     /// Returns "Bearer {token}"
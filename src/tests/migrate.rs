@@ -4,6 +4,7 @@ use rocket::{http::Status, uri};
 use utils::*;
 
 use crate::{
+    db::conn::DB,
     models::request,
     routes,
     tests::e2e::utils::{get_db, prepare_client_with_db},
@@ -24,6 +25,10 @@ fn test_migrate_md5() -> Result<()> {
         .dispatch();
     assert_eq!(resp.status(), Status::Ok);
 
+    let db = client.rocket().state::<DB>().unwrap();
+    let user = db.get_user_by_email(email)?.unwrap();
+    assert!(user.password_hash.starts_with("$argon2id$"));
+
     Ok(())
 }
 
@@ -0,0 +1,36 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+
+/// Last `mysql` migration, which is already applied to database of original
+/// server
+const ORIGINAL_SCHEMA: &str = "2025-09-27-102902_update";
+
+/// `original` build uses its own migrations outside of tests, so they should
+/// be kept in sync manually
+#[test]
+fn test_original_migrations_match_mysql() -> Result<()> {
+    let list = |dir: &str| -> Result<Vec<String>> {
+        let mut names = fs::read_dir(dir)?
+            .map(|e| Ok(e?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>>>()?;
+        names.retain(|n| n.as_str() > ORIGINAL_SCHEMA);
+        names.sort();
+        Ok(names)
+    };
+
+    let mysql = list("migrations/mysql")?;
+    assert_eq!(list("migrations/mysql-original")?, mysql);
+    for name in mysql {
+        for file in ["up.sql", "down.sql"] {
+            let path = |dir: &str| Path::new(dir).join(&name).join(file);
+            assert_eq!(
+                fs::read_to_string(path("migrations/mysql-original"))?,
+                fs::read_to_string(path("migrations/mysql"))?,
+                "{name}/{file} differs",
+            );
+        }
+    }
+
+    Ok(())
+}
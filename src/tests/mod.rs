@@ -1,4 +1,10 @@
+mod cli;
 mod e2e;
+mod migrations;
+
+// compares with queries on raw SQLite connection
+#[cfg(feature = "sqlite")]
+mod bench;
 
 #[cfg(feature = "migrate-md5")]
 mod migrate;